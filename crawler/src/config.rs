@@ -0,0 +1,239 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(about = "OSSE's crawler")]
+pub struct Cli {
+    /// TOML file with the crawler settings (command line arguments take precedence)
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// File with the urls to start crawling from, one per line
+    #[arg(long)]
+    pub seeds_file: Option<PathBuf>,
+    /// Endpoint of the indexer the crawled pages are sent to one by one (only used if batch_size is 1)
+    #[arg(long)]
+    pub indexer_endpoint: Option<String>,
+    /// Endpoint of the indexer the crawled pages are sent to in batches (used unless batch_size is 1)
+    #[arg(long)]
+    pub indexer_batch_endpoint: Option<String>,
+    /// Maximum number of pages being crawled at the same time
+    #[arg(long)]
+    pub concurrency: Option<usize>,
+    /// Maximum number of path segments of the urls we crawl
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+    /// Maximum number of links of each page added to the frontier
+    #[arg(long)]
+    pub links_per_page: Option<usize>,
+    /// Only crawl these domains (focused crawling), can be repeated
+    #[arg(long = "allowed-domain")]
+    pub allowed_domains: Vec<String>,
+    /// Only crawl the urls matching these regexes (focused crawling), can be repeated
+    #[arg(long = "allowed-url-pattern")]
+    pub allowed_url_patterns: Vec<String>,
+    /// Dont include the subdomains of the allowed domains
+    #[arg(long)]
+    pub no_subdomains: bool,
+    /// Never crawl these domains (or their subdomains), can be repeated
+    #[arg(long = "blocked-domain")]
+    pub blocked_domains: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrawlerConfig {
+    pub seeds_file: Option<PathBuf>, //none means the bundled top websites list
+    pub indexer_endpoint: String,
+    //pages are sent to the indexer in batches of batch_size (1 to disable batching), at least every batch_interval_ms
+    pub indexer_batch_endpoint: String,
+    pub batch_size: usize,
+    pub batch_interval_ms: u64,
+    pub compress_batches: bool,
+    //send the extracted text and metadata of html pages instead of their raw html
+    pub extract_documents: bool,
+    //pages waiting for the indexer are spooled to disk, crawling pauses when there are max_outbox_size of them
+    pub max_outbox_size: usize,
+    pub indexer_retry_base_delay_ms: u64,
+    pub max_indexer_retry_delay_secs: u64,
+    pub concurrency: usize,
+    pub max_depth: usize,
+    pub links_per_page: usize,
+    //if any domain or url pattern is allowed we only crawl the urls matching them
+    pub allowed_domains: Vec<String>,
+    pub allowed_url_patterns: Vec<String>,
+    pub include_subdomains: bool,
+    pub blocked_domains: Vec<String>,
+    pub max_queue_size: usize,
+    pub frontier_directory: PathBuf,
+    pub max_connections_per_host: usize,
+    pub min_host_delay_ms: u64,
+    //how we identify ourselves to the websites (and the token we look for in robots.txt)
+    pub user_agent: String,
+    pub contact_url: String,
+    pub accept: String,
+    pub accept_language: String,
+    pub request_timeout_ms: u64,
+    pub connect_timeout_ms: u64,
+    pub max_redirects: usize,
+    pub max_response_size: usize, //in bytes
+    //crawled pages are revisited between these intervals depending on how often they change
+    pub recrawl: bool,
+    pub min_recrawl_interval_secs: u64,
+    pub initial_recrawl_interval_secs: u64,
+    pub max_recrawl_interval_secs: u64,
+    //read the sitemaps of every site we crawl to discover more urls
+    pub sitemaps: bool,
+    //failed urls are retried with exponential backoff, starting at retry_base_delay_secs
+    pub max_retries: u32,
+    pub retry_base_delay_secs: u64,
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        Self {
+            seeds_file: None,
+            indexer_endpoint: "http://127.0.0.1:4444/api/resource".to_string(),
+            indexer_batch_endpoint: "http://127.0.0.1:4444/api/resources".to_string(),
+            batch_size: 50,
+            batch_interval_ms: 2000,
+            compress_batches: true,
+            extract_documents: true,
+            max_outbox_size: 10000,
+            indexer_retry_base_delay_ms: 1000,
+            max_indexer_retry_delay_secs: 5 * 60,
+            concurrency: 64,
+            max_depth: 4,
+            links_per_page: 100,
+            allowed_domains: vec![],
+            allowed_url_patterns: vec![],
+            include_subdomains: true,
+            blocked_domains: vec![],
+            max_queue_size: 100000,
+            frontier_directory: PathBuf::from("crawler_state"),
+            max_connections_per_host: 2,
+            min_host_delay_ms: 1000,
+            user_agent: "OSSE".to_string(),
+            contact_url: "https://github.com/Baitinq/OSSE".to_string(),
+            accept: "text/html,application/xhtml+xml;q=0.9,application/pdf;q=0.8,text/plain;q=0.8,*/*;q=0.1"
+                .to_string(),
+            accept_language: "en;q=1.0,*;q=0.5".to_string(),
+            request_timeout_ms: 30000,
+            connect_timeout_ms: 10000,
+            max_redirects: 10,
+            max_response_size: 10 * 1024 * 1024,
+            recrawl: true,
+            min_recrawl_interval_secs: 60 * 60,
+            initial_recrawl_interval_secs: 60 * 60 * 24,
+            max_recrawl_interval_secs: 60 * 60 * 24 * 30,
+            sitemaps: true,
+            max_retries: 3,
+            retry_base_delay_secs: 30,
+        }
+    }
+}
+
+impl CrawlerConfig {
+    pub fn load(cli: Cli) -> Result<Self, String> {
+        let mut config = match &cli.config {
+            None => CrawlerConfig::default(),
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Error reading {}: {e}", path.display()))?;
+                toml::from_str(&content)
+                    .map_err(|e| format!("Error parsing {}: {e}", path.display()))?
+            }
+        };
+
+        if cli.seeds_file.is_some() {
+            config.seeds_file = cli.seeds_file;
+        }
+        if let Some(indexer_endpoint) = cli.indexer_endpoint {
+            config.indexer_endpoint = indexer_endpoint;
+        }
+        if let Some(indexer_batch_endpoint) = cli.indexer_batch_endpoint {
+            config.indexer_batch_endpoint = indexer_batch_endpoint;
+        }
+        if let Some(concurrency) = cli.concurrency {
+            config.concurrency = concurrency;
+        }
+        if let Some(max_depth) = cli.max_depth {
+            config.max_depth = max_depth;
+        }
+        if let Some(links_per_page) = cli.links_per_page {
+            config.links_per_page = links_per_page;
+        }
+        if !cli.allowed_domains.is_empty() {
+            config.allowed_domains = cli.allowed_domains;
+        }
+        if !cli.allowed_url_patterns.is_empty() {
+            config.allowed_url_patterns = cli.allowed_url_patterns;
+        }
+        if cli.no_subdomains {
+            config.include_subdomains = false;
+        }
+        if !cli.blocked_domains.is_empty() {
+            config.blocked_domains = cli.blocked_domains;
+        }
+
+        if config.concurrency == 0 || config.max_connections_per_host == 0 {
+            return Err("concurrency and max_connections_per_host must be positive".to_string());
+        }
+        if config.min_recrawl_interval_secs > config.max_recrawl_interval_secs {
+            return Err(
+                "min_recrawl_interval_secs cant be bigger than max_recrawl_interval_secs"
+                    .to_string(),
+            );
+        }
+        if config.user_agent.trim().is_empty() {
+            return Err("user_agent cant be empty".to_string());
+        }
+
+        Ok(config)
+    }
+
+    pub fn min_host_delay(&self) -> Duration {
+        Duration::from_millis(self.min_host_delay_ms)
+    }
+
+    pub fn retry_base_delay(&self) -> Duration {
+        Duration::from_secs(self.retry_base_delay_secs)
+    }
+
+    pub fn recrawl_intervals(&self) -> (Duration, Duration, Duration) {
+        (
+            Duration::from_secs(self.min_recrawl_interval_secs),
+            Duration::from_secs(self.initial_recrawl_interval_secs),
+            Duration::from_secs(self.max_recrawl_interval_secs),
+        )
+    }
+
+    //e.g. "OSSE/0.1.0 (+https://github.com/Baitinq/OSSE)"
+    pub fn full_user_agent(&self) -> String {
+        match self.contact_url.is_empty() {
+            true => format!("{}/{}", self.user_agent, env!("CARGO_PKG_VERSION")),
+            false => format!(
+                "{}/{} (+{})",
+                self.user_agent,
+                env!("CARGO_PKG_VERSION"),
+                self.contact_url
+            ),
+        }
+    }
+
+    pub fn seeds(&self) -> Result<Vec<String>, String> {
+        let seeds = match &self.seeds_file {
+            None => include_str!("../top-1000-websites.txt").to_string(),
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("Error reading {}: {e}", path.display()))?,
+        };
+
+        Ok(seeds
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(String::from)
+            .collect())
+    }
+}
@@ -0,0 +1,37 @@
+use crate::error::CrawlError;
+use lib::lib::{FILETYPE_HTML, FILETYPE_PDF, FILETYPE_TEXT};
+
+//the filetype of the documents with that mime type, none if we cant index them
+pub fn filetype_from_mime(mime: &str) -> Option<&'static str> {
+    match mime.trim().to_lowercase().as_str() {
+        "text/html" | "application/xhtml+xml" => Some(FILETYPE_HTML),
+        "text/plain" | "text/markdown" => Some(FILETYPE_TEXT),
+        "application/pdf" | "application/x-pdf" => Some(FILETYPE_PDF),
+        _ => None,
+    }
+}
+
+//for responses without a Content-Type we have to guess from the content itself
+pub fn sniff_filetype(body: &[u8]) -> Result<&'static str, CrawlError> {
+    if body.starts_with(b"%PDF-") {
+        return Ok(FILETYPE_PDF);
+    }
+    //text never has null bytes
+    if body.iter().take(1024).any(|b| *b == 0) {
+        return Err(CrawlError::UnsupportedContentType("binary".to_string()));
+    }
+    Ok(FILETYPE_HTML)
+}
+
+pub async fn pdf_to_text(body: Vec<u8>) -> Result<String, CrawlError> {
+    //parsing pdfs is slow and pdf-extract panics on some malformed files, so we keep it away from the crawling tasks
+    let text = tokio::task::spawn_blocking(move || {
+        std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(&body))
+    })
+    .await
+    .map_err(|e| CrawlError::Extraction(e.to_string()))?
+    .map_err(|_| CrawlError::Extraction("the pdf parser panicked".to_string()))?
+    .map_err(|e| CrawlError::Extraction(e.to_string()))?;
+
+    Ok(text)
+}
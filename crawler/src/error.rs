@@ -0,0 +1,123 @@
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+//why crawling a url failed, so we know whether it is worth trying again
+#[derive(Debug)]
+pub enum CrawlError {
+    Dns(String),
+    Connect(String),
+    Timeout,
+    TooManyRedirects,
+    Redirect(String),  //to a url we dont crawl
+    RobotsTxt(String), //we couldnt get the robots.txt of the host
+    Request(String),   //any other network error
+    RateLimited(Option<Duration>),
+    ClientError(StatusCode),
+    ServerError(StatusCode, Option<Duration>),
+    UnexpectedStatus(StatusCode),
+    TooLarge(usize),
+    UnsupportedContentType(String),
+    Extraction(String), //we couldnt get the text out of the document
+    Indexer(String),
+}
+
+impl CrawlError {
+    //failures that may go away if we try again later
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            CrawlError::Dns(_)
+                | CrawlError::Connect(_)
+                | CrawlError::Timeout
+                | CrawlError::Request(_)
+                | CrawlError::RobotsTxt(_)
+                | CrawlError::RateLimited(_)
+                | CrawlError::ServerError(..)
+                | CrawlError::Indexer(_)
+        )
+    }
+
+    //how long the server asked us to wait before trying again
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            CrawlError::RateLimited(retry_after) | CrawlError::ServerError(_, retry_after) => {
+                *retry_after
+            }
+            _ => None,
+        }
+    }
+
+    pub fn from_status(status: StatusCode, headers: &HeaderMap) -> Self {
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => CrawlError::RateLimited(retry_after(headers)),
+            s if s.is_client_error() => CrawlError::ClientError(s),
+            s if s.is_server_error() => CrawlError::ServerError(s, retry_after(headers)),
+            s => CrawlError::UnexpectedStatus(s),
+        }
+    }
+}
+
+impl From<reqwest::Error> for CrawlError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            return CrawlError::Timeout;
+        }
+        if error.is_redirect() {
+            return CrawlError::TooManyRedirects;
+        }
+
+        //reqwest doesnt tell dns errors apart, but hyper mentions them in the error chain
+        let mut message = error.to_string();
+        let mut source = error.source();
+        while let Some(e) = source {
+            message = format!("{message}: {e}");
+            source = e.source();
+        }
+        match error {
+            _ if message.contains("dns error") => CrawlError::Dns(message),
+            e if e.is_connect() => CrawlError::Connect(message),
+            _ => CrawlError::Request(message),
+        }
+    }
+}
+
+impl fmt::Display for CrawlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CrawlError::Dns(e) => write!(f, "dns error: {e}"),
+            CrawlError::Connect(e) => write!(f, "connection error: {e}"),
+            CrawlError::Timeout => write!(f, "timed out"),
+            CrawlError::TooManyRedirects => write!(f, "too many redirects"),
+            CrawlError::Redirect(url) => write!(f, "redirected to {url}, which we dont crawl"),
+            CrawlError::RobotsTxt(e) => write!(f, "robots.txt unavailable: {e}"),
+            CrawlError::Request(e) => write!(f, "request error: {e}"),
+            CrawlError::RateLimited(_) => write!(f, "rate limited (429)"),
+            CrawlError::ClientError(status) => write!(f, "client error {status}"),
+            CrawlError::UnexpectedStatus(status) => write!(f, "unexpected status {status}"),
+            CrawlError::ServerError(status, _) => write!(f, "server error {status}"),
+            CrawlError::TooLarge(max_size) => {
+                write!(f, "response too large, max size is {max_size} bytes")
+            }
+            CrawlError::UnsupportedContentType(content_type) => {
+                write!(f, "unsupported content type ({content_type})")
+            }
+            CrawlError::Extraction(e) => write!(f, "error extracting the text: {e}"),
+            CrawlError::Indexer(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+//Retry-After is either a number of seconds or an http date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(SystemTime::now())
+            .ok(),
+    }
+}
@@ -0,0 +1,115 @@
+use crate::config::CrawlerConfig;
+use crate::error::CrawlError;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+use regex::bytes::Regex;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, ACCEPT_LANGUAGE, CONTENT_TYPE};
+use reqwest::{redirect, Client, Response};
+use std::sync::OnceLock;
+use std::time::Duration;
+use url::Url;
+
+//how far into the page we look for <meta> charset declarations (same as the html spec)
+const META_PRESCAN_SIZE: usize = 1024;
+
+//pages are fetched with a client that doesnt follow redirects (follow_redirects false), so we can follow
+//them ourselves and know where their content came from
+pub fn build_http_client(config: &CrawlerConfig, follow_redirects: bool) -> Result<Client, String> {
+    let header = |name: &str, value: &str| {
+        HeaderValue::from_str(value).map_err(|e| format!("Invalid {name} header ({value}): {e}"))
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, header("Accept", &config.accept)?);
+    headers.insert(
+        ACCEPT_LANGUAGE,
+        header("Accept-Language", &config.accept_language)?,
+    );
+
+    Client::builder()
+        .user_agent(config.full_user_agent())
+        .default_headers(headers)
+        .timeout(Duration::from_millis(config.request_timeout_ms))
+        .connect_timeout(Duration::from_millis(config.connect_timeout_ms))
+        .redirect(match follow_redirects {
+            true => redirect::Policy::limited(config.max_redirects),
+            false => redirect::Policy::none(),
+        })
+        .build()
+        .map_err(|e| format!("Error building the http client: {e}"))
+}
+
+//reads the whole body, giving up if it is bigger than max_size bytes so huge responses dont exhaust our memory
+pub async fn read_body(mut response: Response, max_size: usize) -> Result<Vec<u8>, CrawlError> {
+    if response.content_length().unwrap_or(0) > max_size as u64 {
+        return Err(CrawlError::TooLarge(max_size));
+    }
+
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_size {
+            return Err(CrawlError::TooLarge(max_size));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body)
+}
+
+//decodes the body to utf-8. like browsers do, the encoding comes from the BOM, then the Content-Type header,
+//then (for html) the <meta> tags and otherwise we guess it from the content itself
+pub fn decode_body(body: &[u8], headers: &HeaderMap, url: &Url, is_html: bool) -> String {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(body) {
+        return encoding
+            .decode_without_bom_handling(&body[bom_length..])
+            .0
+            .into_owned();
+    }
+
+    let encoding = header_charset(headers)
+        .or_else(|| if is_html { meta_charset(body) } else { None })
+        .unwrap_or_else(|| {
+            let mut detector = EncodingDetector::new();
+            detector.feed(body, true);
+            //the tld helps telling apart legacy encodings, e.g. .jp pages are probably Shift_JIS
+            let tld = url
+                .domain()
+                .and_then(|d| d.rsplit('.').next())
+                .map(str::as_bytes);
+            detector.guess(tld, true)
+        });
+
+    encoding.decode_without_bom_handling(body).0.into_owned()
+}
+
+fn header_charset(headers: &HeaderMap) -> Option<&'static Encoding> {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .split(';')
+                .filter_map(|param| param.trim().split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("charset"))
+                .map(|(_, charset)| charset.trim().trim_matches('"').to_string())
+        })
+        .and_then(|charset| Encoding::for_label(charset.as_bytes()))
+}
+
+//looks for <meta charset="..."> or <meta http-equiv="Content-Type" content="...; charset=..."> in the
+//first bytes of the page, which is where browsers look for them too
+fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    static META_CHARSET: OnceLock<Regex> = OnceLock::new();
+    let regex = META_CHARSET.get_or_init(|| {
+        Regex::new(r#"(?i-u)<meta[^>]*?charset\s*=\s*["']?\s*([a-z0-9_:.\-]+)"#).unwrap()
+    });
+
+    let start = &body[..body.len().min(META_PRESCAN_SIZE)];
+    let label = regex.captures(start)?.get(1)?.as_bytes();
+    match Encoding::for_label(label)? {
+        //the bytes we just read are ascii compatible, so the page cant really be utf-16
+        encoding if encoding == UTF_16LE || encoding == UTF_16BE => Some(UTF_8),
+        encoding if encoding == X_USER_DEFINED => Some(WINDOWS_1252),
+        encoding => Some(encoding),
+    }
+}
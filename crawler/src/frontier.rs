@@ -0,0 +1,578 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+use url::Url;
+
+const JOURNAL_FILE: &str = "frontier.log";
+const SECONDS_PER_YEAR: u64 = 60 * 60 * 24 * 365;
+
+//weights of the signals used to prioritise urls
+const DEPTH_WEIGHT: f64 = 1.0;
+const INLINK_WEIGHT: f64 = 1.5;
+const HOST_WEIGHT: f64 = 1.0;
+const FRESHNESS_WEIGHT: f64 = 2.0;
+const SITEMAP_PRIORITY_WEIGHT: f64 = 2.0;
+
+//priority of the urls that dont come from a sitemap (the default of the sitemaps protocol)
+const DEFAULT_PRIORITY: f64 = 0.5;
+
+//a url to crawl along with how far it is from the seeds
+#[derive(Debug, Clone)]
+pub struct FrontierEntry {
+    pub url: String,
+    pub depth: u32,
+}
+
+struct PendingUrl {
+    depth: u32,
+    inlinks: u32,
+    freshness: f64,
+    //hints from sitemaps
+    priority: f64,
+    modified_at: Option<u64>,
+    key: (i64, u64), //position in the queue
+}
+
+struct FrontierState {
+    //ordered by (score, insertion order), the best url is the last one
+    queue: BTreeSet<(i64, u64, String)>,
+    pending: HashMap<String, PendingUrl>,
+    //every url we have ever queued, so we never crawl the same page twice
+    seen: HashSet<String>,
+    //number of urls crawled per host, so a single site doesnt take over the crawl
+    crawled_per_host: HashMap<String, u32>,
+    next_sequence: u64,
+    journal: File,
+}
+
+//priority queue of urls to crawl that survives restarts.
+//every change is appended to a journal which is replayed on startup:
+//"+ depth inlinks url" when a url is discovered, "- url" when it is crawled, "x url" when it is evicted,
+//"r depth url" when a crawled url is queued again to be recrawled and "h priority modified_at url"
+//when a sitemap gives us hints about a queued url (modified_at is 0 if unknown).
+pub struct Frontier {
+    state: Mutex<FrontierState>,
+    new_urls: Notify,
+    max_size: usize,
+}
+
+impl Frontier {
+    pub fn open(directory: &Path, max_size: usize) -> std::io::Result<Self> {
+        std::fs::create_dir_all(directory)?;
+        let journal_path = directory.join(JOURNAL_FILE);
+
+        let tmp_path = PathBuf::from(format!("{}.tmp", journal_path.display()));
+        let mut state = FrontierState {
+            queue: BTreeSet::new(),
+            pending: HashMap::new(),
+            seen: HashSet::new(),
+            crawled_per_host: HashMap::new(),
+            next_sequence: 0,
+            journal: File::create(&tmp_path)?,
+        };
+        let crawled = Self::replay(&journal_path, &mut state)?;
+        log::info!(
+            "Resuming frontier: {} queued urls, {} seen urls",
+            state.pending.len(),
+            state.seen.len()
+        );
+
+        //compact the journal so it doesnt grow forever
+        let mut compacted = BufWriter::new(&state.journal);
+        for url in &crawled {
+            writeln!(compacted, "- {url}")?;
+        }
+        for (url, pending) in &state.pending {
+            writeln!(compacted, "+ {} {} {}", pending.depth, pending.inlinks, url)?;
+            if pending.priority != DEFAULT_PRIORITY || pending.modified_at.is_some() {
+                let modified_at = pending.modified_at.unwrap_or(0);
+                writeln!(compacted, "h {} {} {}", pending.priority, modified_at, url)?;
+            }
+        }
+        compacted.flush()?;
+        drop(compacted);
+        std::fs::rename(&tmp_path, &journal_path)?;
+        state.journal = OpenOptions::new().append(true).open(&journal_path)?;
+
+        Ok(Self {
+            state: Mutex::new(state),
+            new_urls: Notify::new(),
+            max_size,
+        })
+    }
+
+    //rebuilds the state from the journal, returns the crawled urls
+    fn replay(journal_path: &Path, state: &mut FrontierState) -> std::io::Result<HashSet<String>> {
+        let mut crawled = HashSet::new();
+        if !journal_path.exists() {
+            return Ok(crawled);
+        }
+
+        for line in BufReader::new(File::open(journal_path)?).lines() {
+            let line = line?;
+            let fields: Vec<&str> = line.splitn(4, ' ').collect();
+            match fields.as_slice() {
+                ["+", depth, inlinks, url] => match (depth.parse(), inlinks.parse()) {
+                    //we dont evict anything while replaying
+                    (Ok(depth), Ok(inlinks)) if !crawled.contains(*url) => {
+                        state.discover(url, depth, inlinks, usize::MAX);
+                    }
+                    _ => {}
+                },
+                ["-", url] => {
+                    state.remove(url);
+                    state.seen.insert(url.to_string());
+                    crawled.insert(url.to_string());
+                    *state.crawled_per_host.entry(host(url)).or_default() += 1;
+                }
+                ["x", url] => {
+                    state.remove(url);
+                    state.seen.remove(*url);
+                }
+                ["h", priority, modified_at, url] => {
+                    if let (Ok(priority), Ok(modified_at)) = (priority.parse(), modified_at.parse())
+                    {
+                        let modified_at = Some(modified_at).filter(|m: &u64| *m > 0);
+                        state.apply_hints(url, priority, modified_at);
+                    }
+                }
+                ["r", depth, url] => match depth.parse() {
+                    Ok(depth) if crawled.remove(*url) => {
+                        state.seen.remove(*url);
+                        state.discover(url, depth, 1, usize::MAX);
+                    }
+                    _ => {}
+                },
+                _ => log::warn!("Ignoring invalid frontier journal line: {:?}", line),
+            }
+        }
+
+        Ok(crawled)
+    }
+
+    //queues the url (or counts another link to it if it is already queued). returns whether it was queued.
+    pub fn push(&self, url: &str, depth: u32) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let queued = state.discover(url, depth, 1, self.max_size);
+
+        //only links to queued urls change the state
+        if state.pending.contains_key(url) {
+            if let Err(e) = writeln!(state.journal, "+ {depth} 1 {url}") {
+                log::error!("Error writing to the frontier journal: {}", e);
+            }
+        }
+        drop(state);
+
+        if queued {
+            self.new_urls.notify();
+        }
+        queued
+    }
+
+    //queues a url listed in a sitemap, with the priority (0 to 1) and last modification time (unix seconds) it gives us
+    pub fn push_from_sitemap(
+        &self,
+        url: &str,
+        depth: u32,
+        priority: Option<f64>,
+        modified_at: Option<u64>,
+    ) -> bool {
+        let mut state = self.state.lock().unwrap();
+        //a sitemap isnt really a link to the url, so it doesnt count as an inlink
+        let queued = state.discover(url, depth, 0, self.max_size);
+
+        if state.pending.contains_key(url) {
+            let priority = priority.unwrap_or(DEFAULT_PRIORITY).clamp(0.0, 1.0);
+            state.apply_hints(url, priority, modified_at);
+            let journaled = writeln!(state.journal, "+ {depth} 0 {url}").and_then(|_| {
+                let modified_at = modified_at.unwrap_or(0);
+                writeln!(state.journal, "h {priority} {modified_at} {url}")
+            });
+            if let Err(e) = journaled {
+                log::error!("Error writing to the frontier journal: {}", e);
+            }
+        }
+        drop(state);
+
+        if queued {
+            self.new_urls.notify();
+        }
+        queued
+    }
+
+    //queues an already crawled url again. returns whether it was queued.
+    pub fn recrawl(&self, url: &str, depth: u32) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.contains_key(url) {
+            return false;
+        }
+
+        state.seen.remove(url);
+        let queued = state.discover(url, depth, 1, self.max_size);
+        if queued {
+            if let Err(e) = writeln!(state.journal, "r {depth} {url}") {
+                log::error!("Error writing to the frontier journal: {}", e);
+            }
+        } else {
+            state.seen.insert(url.to_string());
+        }
+        drop(state);
+
+        if queued {
+            self.new_urls.notify();
+        }
+        queued
+    }
+
+    //waits for the highest priority url to crawl
+    pub async fn pop(&self) -> FrontierEntry {
+        loop {
+            if let Some(entry) = self.state.lock().unwrap().pop_best() {
+                return entry;
+            }
+            self.new_urls.notified().await;
+        }
+    }
+
+    //marks the url as crawled (whether it succeeded or not) so it isnt crawled again after a restart
+    pub fn complete(&self, url: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Err(e) = writeln!(state.journal, "- {url}") {
+            log::error!("Error writing to the frontier journal: {}", e);
+        }
+    }
+
+    //the url was crawled under another one (it is part of its redirect chain), so we dont crawl it again
+    pub fn complete_alias(&self, url: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.remove(url);
+        state.seen.insert(url.to_string());
+        if let Err(e) = writeln!(state.journal, "- {url}") {
+            log::error!("Error writing to the frontier journal: {}", e);
+        }
+    }
+}
+
+impl FrontierState {
+    fn discover(&mut self, url: &str, depth: u32, inlinks: u32, max_size: usize) -> bool {
+        if let Some(pending) = self.pending.get_mut(url) {
+            pending.depth = pending.depth.min(depth);
+            pending.inlinks += inlinks;
+            self.requeue(url);
+            return false;
+        }
+        if self.seen.contains(url) {
+            return false;
+        }
+
+        let mut pending = PendingUrl {
+            depth,
+            inlinks,
+            freshness: estimate_freshness(url),
+            priority: DEFAULT_PRIORITY,
+            modified_at: None,
+            key: (0, 0),
+        };
+        let score = self.score(url, &pending);
+
+        if self.pending.len() >= max_size {
+            //we make space by evicting the worst url, unless the new one is even worse
+            match self.queue.iter().next().cloned() {
+                Some((worst_score, _, worst_url)) if worst_score < score => {
+                    self.remove(&worst_url);
+                    self.seen.remove(&worst_url);
+                    if let Err(e) = writeln!(self.journal, "x {worst_url}") {
+                        log::error!("Error writing to the frontier journal: {}", e);
+                    }
+                }
+                _ => return false,
+            }
+        }
+
+        pending.key = (score, self.next_sequence);
+        self.next_sequence += 1;
+        self.queue.insert((score, pending.key.1, url.to_string()));
+        self.pending.insert(url.to_string(), pending);
+        self.seen.insert(url.to_string());
+        true
+    }
+
+    fn pop_best(&mut self) -> Option<FrontierEntry> {
+        //scores depend on how many pages of the host we crawled since the url was queued,
+        //so we recalculate the best one and put it back if it changed
+        loop {
+            let (score, sequence, url) = self.queue.iter().next_back().cloned()?;
+            let pending = &self.pending[&url];
+            if self.score(&url, pending) != score {
+                self.requeue(&url);
+                continue;
+            }
+
+            self.queue.remove(&(score, sequence, url.clone()));
+            let pending = self.pending.remove(&url)?;
+            *self.crawled_per_host.entry(host(&url)).or_default() += 1;
+            return Some(FrontierEntry {
+                url,
+                depth: pending.depth,
+            });
+        }
+    }
+
+    fn apply_hints(&mut self, url: &str, priority: f64, modified_at: Option<u64>) {
+        if let Some(pending) = self.pending.get_mut(url) {
+            pending.priority = priority;
+            if let Some(modified_at) = modified_at {
+                pending.modified_at = Some(modified_at);
+                pending.freshness = freshness_since(modified_at);
+            }
+            self.requeue(url);
+        }
+    }
+
+    //updates the position in the queue of a pending url after its score changed
+    fn requeue(&mut self, url: &str) {
+        let score = match self.pending.get(url) {
+            None => return,
+            Some(pending) => self.score(url, pending),
+        };
+        let pending = self.pending.get_mut(url).unwrap();
+        let (old_score, sequence) = pending.key;
+        pending.key = (score, sequence);
+        self.queue.remove(&(old_score, sequence, url.to_string()));
+        self.queue.insert((score, sequence, url.to_string()));
+    }
+
+    fn remove(&mut self, url: &str) {
+        if let Some(pending) = self.pending.remove(url) {
+            let (score, sequence) = pending.key;
+            self.queue.remove(&(score, sequence, url.to_string()));
+        }
+    }
+
+    //higher is better. scaled to an integer so it can be ordered.
+    fn score(&self, url: &str, pending: &PendingUrl) -> i64 {
+        let crawled_from_host = self.crawled_per_host.get(&host(url)).copied().unwrap_or(0);
+
+        let score = -DEPTH_WEIGHT * pending.depth as f64
+            + INLINK_WEIGHT * (1.0 + pending.inlinks as f64).ln()
+            - HOST_WEIGHT * (1.0 + crawled_from_host as f64).ln()
+            + FRESHNESS_WEIGHT * pending.freshness
+            + SITEMAP_PRIORITY_WEIGHT * (pending.priority - DEFAULT_PRIORITY);
+
+        (score * 1000.0) as i64
+    }
+}
+
+fn host(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+//between 0 and 1. urls with an old year in their path (e.g. /2012/05/some-post) are probably old archive pages.
+fn estimate_freshness(url: &str) -> f64 {
+    let year = url
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|s| s.len() == 4)
+        .filter_map(|s| s.parse::<u32>().ok())
+        .filter(|y| (1990..2100).contains(y))
+        .max();
+
+    match year {
+        None => 0.5,
+        Some(year) => {
+            let current_year = 1970 + (now() / SECONDS_PER_YEAR) as u32;
+            1.0 / (1.0 + current_year.saturating_sub(year) as f64)
+        }
+    }
+}
+
+//same scale as estimate_freshness, but we know exactly when the page was modified
+fn freshness_since(modified_at: u64) -> f64 {
+    1.0 / (1.0 + now().saturating_sub(modified_at) as f64 / SECONDS_PER_YEAR as f64)
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //an empty directory for the frontier of a test
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("osse-frontier-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn pop(frontier: &Frontier) -> Option<String> {
+        frontier.state.lock().unwrap().pop_best().map(|e| e.url)
+    }
+
+    fn pending(frontier: &Frontier) -> Vec<String> {
+        let mut urls: Vec<String> = frontier
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .keys()
+            .cloned()
+            .collect();
+        urls.sort();
+        urls
+    }
+
+    #[test]
+    fn pops_the_best_url_first() {
+        let directory = test_directory("order");
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        frontier.push("https://a.com/deep", 3);
+        frontier.push("https://b.com/shallow", 0);
+
+        assert_eq!(pop(&frontier).as_deref(), Some("https://b.com/shallow"));
+        assert_eq!(pop(&frontier).as_deref(), Some("https://a.com/deep"));
+        assert_eq!(pop(&frontier), None);
+    }
+
+    #[test]
+    fn urls_are_only_queued_once() {
+        let directory = test_directory("seen");
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        assert!(frontier.push("https://a.com/", 0));
+        assert!(!frontier.push("https://a.com/", 1));
+
+        let url = pop(&frontier).unwrap();
+        frontier.complete(&url);
+        assert!(!frontier.push("https://a.com/", 0));
+    }
+
+    #[test]
+    fn replays_the_journal_after_a_crash() {
+        let directory = test_directory("replay");
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        frontier.push("https://a.com/1", 0);
+        frontier.push("https://a.com/2", 1);
+        frontier.push("https://a.com/3", 2);
+        let crawled = pop(&frontier).unwrap();
+        frontier.complete(&crawled);
+        //popped but never completed, so it has to be crawled again
+        let _interrupted = pop(&frontier).unwrap();
+        drop(frontier);
+
+        //a line cut in half by the crash
+        let mut journal = OpenOptions::new()
+            .append(true)
+            .open(directory.join(JOURNAL_FILE))
+            .unwrap();
+        write!(journal, "+ 1").unwrap();
+        drop(journal);
+
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        assert_eq!(
+            pending(&frontier),
+            vec!["https://a.com/2", "https://a.com/3"]
+        );
+        assert!(!frontier.push(&crawled, 0));
+
+        //and the compacted journal gives the same state
+        drop(frontier);
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        assert_eq!(
+            pending(&frontier),
+            vec!["https://a.com/2", "https://a.com/3"]
+        );
+        assert!(!frontier.push(&crawled, 0));
+    }
+
+    #[test]
+    fn evicts_the_worst_url_when_full() {
+        let directory = test_directory("evict");
+        let frontier = Frontier::open(&directory, 2).unwrap();
+        assert!(frontier.push("https://a.com/0", 0));
+        assert!(frontier.push("https://a.com/5", 5));
+        assert!(frontier.push("https://a.com/1", 1));
+        assert_eq!(
+            pending(&frontier),
+            vec!["https://a.com/0", "https://a.com/1"]
+        );
+
+        //worse than everything queued
+        assert!(!frontier.push("https://a.com/9", 9));
+        //evicted urls can come back
+        assert!(!frontier.push("https://a.com/5", 5));
+        assert!(frontier.push("https://a.com/5", 0));
+        assert_eq!(
+            pending(&frontier),
+            vec!["https://a.com/0", "https://a.com/5"]
+        );
+        drop(frontier);
+
+        let frontier = Frontier::open(&directory, 2).unwrap();
+        assert_eq!(
+            pending(&frontier),
+            vec!["https://a.com/0", "https://a.com/5"]
+        );
+    }
+
+    #[test]
+    fn crawled_urls_can_be_recrawled() {
+        let directory = test_directory("recrawl");
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        frontier.push("https://a.com/", 0);
+        let url = pop(&frontier).unwrap();
+        frontier.complete(&url);
+
+        assert!(frontier.recrawl(&url, 0));
+        //already queued
+        assert!(!frontier.recrawl(&url, 0));
+        drop(frontier);
+
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        assert_eq!(pending(&frontier), vec!["https://a.com/"]);
+        let url = pop(&frontier).unwrap();
+        frontier.complete(&url);
+        drop(frontier);
+
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        assert!(pending(&frontier).is_empty());
+        assert!(!frontier.push(&url, 0));
+    }
+
+    #[test]
+    fn aliases_are_not_crawled() {
+        let directory = test_directory("alias");
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        frontier.push("https://a.com/old", 0);
+        frontier.complete_alias("https://a.com/old");
+        frontier.complete_alias("https://a.com/new");
+
+        assert!(pending(&frontier).is_empty());
+        assert!(!frontier.push("https://a.com/new", 0));
+        drop(frontier);
+
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        assert!(pending(&frontier).is_empty());
+        assert!(!frontier.push("https://a.com/old", 0));
+    }
+
+    #[test]
+    fn sitemap_hints_survive_a_restart() {
+        let directory = test_directory("hints");
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        frontier.push_from_sitemap("https://a.com/important", 0, Some(1.0), None);
+        frontier.push_from_sitemap("https://a.com/unimportant", 0, Some(0.0), None);
+        drop(frontier);
+
+        let frontier = Frontier::open(&directory, 100).unwrap();
+        assert_eq!(pop(&frontier).as_deref(), Some("https://a.com/important"));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::config::CrawlerConfig;
+use crate::error::CrawlError;
+use crate::outbox::Outbox;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use lib::lib::CrawledResource;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::{Client, StatusCode};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+//sends the crawled resources to the indexer. they are spooled to the outbox first, and delivered from it
+//in batches of batch_size to /api/resources (as gzipped ndjson, or one by one to /api/resource if batch_size
+//is 1) once we have batch_size of them or batch_interval passed. failed deliveries are retried with
+//exponential backoff, and crawling is paused when the indexer tells us to slow down (429/503) or the
+//outbox is full.
+pub struct IndexerClient {
+    http_client: Client,
+    outbox: Outbox,
+    endpoint: String,
+    batch_endpoint: String,
+    batch_size: usize,
+    batch_interval: Duration,
+    compress: bool,
+    max_outbox_size: usize,
+    retry_base_delay: Duration,
+    max_retry_delay: Duration,
+    //when the indexer asked us to slow down until
+    throttled_until: Mutex<Option<Instant>>,
+    batch_full: Notify,
+    delivered: Notify,
+}
+
+impl IndexerClient {
+    pub fn new(http_client: Client, outbox: Outbox, config: &CrawlerConfig) -> Self {
+        Self {
+            http_client,
+            outbox,
+            endpoint: config.indexer_endpoint.clone(),
+            batch_endpoint: config.indexer_batch_endpoint.clone(),
+            batch_size: config.batch_size.max(1),
+            batch_interval: Duration::from_millis(config.batch_interval_ms),
+            compress: config.compress_batches,
+            max_outbox_size: config.max_outbox_size.max(1),
+            retry_base_delay: Duration::from_millis(config.indexer_retry_base_delay_ms),
+            max_retry_delay: Duration::from_secs(config.max_indexer_retry_delay_secs),
+            throttled_until: Mutex::new(None),
+            batch_full: Notify::new(),
+            delivered: Notify::new(),
+        }
+    }
+
+    //returns once the resource is safely in the outbox, it is delivered in the background by run()
+    pub fn push(&self, resource: CrawledResource) -> Result<(), String> {
+        let waiting = self
+            .outbox
+            .push(&resource)
+            .map_err(|e| format!("Error writing {} to the outbox: {}", resource.url, e))?;
+        if waiting >= self.batch_size {
+            self.batch_full.notify();
+        }
+        Ok(())
+    }
+
+    //waits while the indexer wants us to slow down or there are too many resources waiting for it
+    pub async fn ready(&self) {
+        loop {
+            let throttled_until = *self.throttled_until.lock().unwrap();
+            if let Some(throttled_until) = throttled_until.filter(|t| *t > Instant::now()) {
+                tokio::time::delay_until(throttled_until).await;
+                continue;
+            }
+
+            if self.outbox.len() < self.max_outbox_size {
+                return;
+            }
+            log::debug!("Outbox full, waiting for the indexer");
+            //we check again from time to time in case we missed a notification
+            let _ = tokio::time::timeout(self.batch_interval, self.delivered.notified()).await;
+        }
+    }
+
+    //delivers the resources in the outbox, has to be running for anything to get to the indexer
+    pub async fn run(&self) {
+        let mut failures = 0;
+        loop {
+            let _ = tokio::time::timeout(self.batch_interval, self.batch_full.notified()).await;
+
+            loop {
+                let batch = self.outbox.peek(self.batch_size);
+                if batch.is_empty() {
+                    break;
+                }
+                let ids: Vec<u64> = batch.iter().map(|(id, _)| *id).collect();
+                let resources: Vec<&CrawledResource> = batch.iter().map(|(_, r)| r).collect();
+
+                let result = match resources.as_slice() {
+                    [resource] if self.batch_size == 1 => self.send(resource).await,
+                    resources => self.send_batch(resources).await,
+                };
+                match result {
+                    Ok(()) => {
+                        failures = 0;
+                        self.outbox.remove(&ids);
+                        self.delivered.notify();
+                    }
+                    //the indexer will never take them, no point in blocking the others
+                    Err(e) if !e.is_transient() => {
+                        log::error!(
+                            "The indexer rejected {} resources, moving them out of the outbox: {}",
+                            ids.len(),
+                            e
+                        );
+                        self.outbox.reject(&ids);
+                        self.delivered.notify();
+                    }
+                    Err(e) => {
+                        failures += 1;
+                        let delay = e.retry_after().unwrap_or_else(|| {
+                            self.retry_base_delay * 2u32.saturating_pow(failures - 1)
+                        });
+                        let delay = delay.min(self.max_retry_delay);
+                        log::warn!(
+                            "Error pushing to the indexer ({} resources waiting), retrying in {:?}: {}",
+                            self.outbox.len(),
+                            delay,
+                            e
+                        );
+
+                        //the indexer is overloaded, so we stop crawling until we can talk to it again
+                        if is_backpressure(&e) {
+                            *self.throttled_until.lock().unwrap() = Some(Instant::now() + delay);
+                        }
+                        tokio::time::delay_for(delay).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn send(&self, resource: &CrawledResource) -> Result<(), CrawlError> {
+        log::debug!("Pushin to indexer");
+
+        let response = self
+            .http_client
+            .post(&self.endpoint)
+            .json(resource)
+            .send()
+            .await?;
+        check_response(response).await
+    }
+
+    async fn send_batch(&self, resources: &[&CrawledResource]) -> Result<(), CrawlError> {
+        log::debug!("Pushin {} resources to indexer", resources.len());
+
+        let mut body = vec![];
+        for resource in resources {
+            serde_json::to_writer(&mut body, resource).map_err(|e| {
+                CrawlError::Indexer(format!("Error serializing {}: {}", resource.url, e))
+            })?;
+            body.push(b'\n');
+        }
+
+        let mut request = self
+            .http_client
+            .post(&self.batch_endpoint)
+            .header(CONTENT_TYPE, "application/x-ndjson");
+        if self.compress {
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            body = encoder
+                .write_all(&body)
+                .and_then(|_| encoder.finish())
+                .map_err(|e| CrawlError::Indexer(format!("Error compressing the batch: {e}")))?;
+            request = request.header(CONTENT_ENCODING, "gzip");
+        }
+
+        let response = request.body(body).send().await?;
+        check_response(response).await
+    }
+}
+
+async fn check_response(response: reqwest::Response) -> Result<(), CrawlError> {
+    if !response.status().is_success() {
+        return Err(CrawlError::from_status(
+            response.status(),
+            response.headers(),
+        ));
+    }
+    log::debug!("Pushed to indexer {:?}", response.text().await);
+    Ok(())
+}
+
+fn is_backpressure(error: &CrawlError) -> bool {
+    matches!(
+        error,
+        CrawlError::RateLimited(_) | CrawlError::ServerError(StatusCode::SERVICE_UNAVAILABLE, _)
+    )
+}
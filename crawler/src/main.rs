@@ -1,122 +1,637 @@
+mod config;
+mod documents;
+mod error;
+mod fetch;
+mod frontier;
+mod indexer_client;
+mod outbox;
+mod recrawl;
+mod retry;
+mod robots;
+mod scheduler;
+mod scope;
+mod sitemap;
+
+use clap::Parser;
+use config::{Cli, CrawlerConfig};
+use error::CrawlError;
+use frontier::{Frontier, FrontierEntry};
+use indexer_client::IndexerClient;
 use itertools::Itertools;
+use lib::extraction;
 use lib::lib::*;
-use rand::seq::IteratorRandom;
-use reqwest::{Client, Response, StatusCode};
+use outbox::Outbox;
+use recrawl::{RecrawlSchedule, Validators};
+use reqwest::header::{
+    HeaderMap, CONTENT_LANGUAGE, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LAST_MODIFIED, LOCATION,
+};
+use reqwest::{Client, StatusCode};
+use retry::Retries;
+use robots::{RobotsCache, RobotsTxt};
+use scheduler::HostScheduler;
+use scope::CrawlScope;
+use sitemap::Sitemap;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use url::Url;
 
+//how often we look for pages that are due for a recrawl
+const RECRAWL_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+//how often we look for failed urls that are due for another attempt
+const RETRY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+//maximum number of sitemaps (including indexes) we read from a single site
+const MAX_SITEMAPS_PER_SITE: usize = 100;
+
 #[tokio::main]
 async fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
     log::info!("Hello, world! Im the crawler!");
 
-    let root_urls = include_str!("../top-1000-websites.txt");
-    let root_urls = root_urls.split('\n').collect();
+    let config = match CrawlerConfig::load(Cli::parse()) {
+        Err(e) => {
+            log::error!("{}", e);
+            return;
+        }
+        Ok(config) => config,
+    };
+    log::debug!("Config: {:?}", config);
+
+    let root_urls = match config.seeds() {
+        Err(e) => {
+            log::error!("{}", e);
+            return;
+        }
+        Ok(root_urls) => root_urls,
+    };
+
+    let scope = match CrawlScope::new(&config) {
+        Err(e) => {
+            log::error!("{}", e);
+            return;
+        }
+        Ok(scope) => scope,
+    };
+    if scope.is_focused() {
+        log::info!("Focused crawling: only urls in the allowed domains or patterns are crawled");
+    }
+
+    let frontier = match Frontier::open(&config.frontier_directory, config.max_queue_size) {
+        Err(e) => {
+            log::error!(
+                "Error opening the frontier ({}): {}",
+                config.frontier_directory.display(),
+                e
+            );
+            return;
+        }
+        Ok(frontier) => frontier,
+    };
+
+    let (min_interval, initial_interval, max_interval) = config.recrawl_intervals();
+    let recrawl_schedule = match RecrawlSchedule::open(
+        &config.frontier_directory,
+        min_interval,
+        initial_interval,
+        max_interval,
+    ) {
+        Err(e) => {
+            log::error!(
+                "Error opening the recrawl schedule ({}): {}",
+                config.frontier_directory.display(),
+                e
+            );
+            return;
+        }
+        Ok(recrawl_schedule) => recrawl_schedule,
+    };
+
+    let retries = match Retries::open(
+        &config.frontier_directory,
+        config.max_retries,
+        config.retry_base_delay(),
+    ) {
+        Err(e) => {
+            log::error!(
+                "Error opening the dead letters file ({}): {}",
+                config.frontier_directory.display(),
+                e
+            );
+            return;
+        }
+        Ok(retries) => retries,
+    };
+
+    let outbox = match Outbox::open(&config.frontier_directory) {
+        Err(e) => {
+            log::error!(
+                "Error opening the outbox ({}): {}",
+                config.frontier_directory.display(),
+                e
+            );
+            return;
+        }
+        Ok(outbox) => outbox,
+    };
+
+    let (http_client, page_client) = match (
+        fetch::build_http_client(&config, true),
+        fetch::build_http_client(&config, false),
+    ) {
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("{}", e);
+            return;
+        }
+        (Ok(http_client), Ok(page_client)) => (http_client, page_client),
+    };
+    log::info!("Crawling as {}", config.full_user_agent());
 
-    let max_queue_size = 2222;
+    let state = CrawlerState {
+        indexer_client: IndexerClient::new(http_client.clone(), outbox, &config),
+        http_client,
+        page_client,
+        robots_cache: RobotsCache::new(&config.user_agent),
+        host_scheduler: HostScheduler::new(
+            config.min_host_delay(),
+            config.max_connections_per_host,
+        ),
+        frontier,
+        recrawl_schedule,
+        retries,
+        sitemap_origins: Mutex::new(HashSet::new()),
+        scope,
+        config,
+    };
 
-    let http_client = reqwest::Client::new();
+    crawler(Arc::new(state), root_urls).await;
+}
 
-    crawler(http_client, root_urls, max_queue_size).await;
+//state shared by all the crawling tasks
+struct CrawlerState {
+    http_client: Client,
+    //doesnt follow redirects, crawl_url does
+    page_client: Client,
+    indexer_client: IndexerClient,
+    robots_cache: RobotsCache,
+    host_scheduler: HostScheduler,
+    frontier: Frontier,
+    recrawl_schedule: RecrawlSchedule,
+    retries: Retries,
+    //sites whose sitemaps we already read
+    sitemap_origins: Mutex<HashSet<String>>,
+    scope: CrawlScope,
+    config: CrawlerConfig,
 }
 
-async fn crawler(http_client: Client, root_urls: Vec<&str>, max_queue_size: usize) {
+async fn crawler(state: Arc<CrawlerState>, root_urls: Vec<String>) {
     log::debug!("Starting to crawl!");
 
-    //add root urls to queue
-    let (tx_crawling_queue, rx_crawling_queue) =
-        async_channel::bounded::<String>(std::cmp::max(max_queue_size, root_urls.len()));
+    //add root urls to queue (the ones we already crawled before a restart are ignored)
     for url in root_urls {
-        tx_crawling_queue.send(url.to_string()).await.unwrap();
+        match Url::parse(&url) {
+            Ok(parsed_url) if state.scope.contains(&parsed_url) => _ = state.frontier.push(&url, 0),
+            _ => log::info!("Ignoring seed {} (invalid or out of scope)", url),
+        }
     }
 
-    //and start crawling
-    loop {
-        //even if we clone, the underlying queue implementation is still shared
-        let tx_crawling_queue = tx_crawling_queue.clone();
-        let rx_crawling_queue = rx_crawling_queue.clone();
-        //blocks - we move it up here as to at least block for next url and not endesly spawn tasks
-        let url = rx_crawling_queue.recv().await.unwrap();
-        let http_client = http_client.clone();
+    //delivers the crawled pages to the indexer in batches
+    let indexer_state = state.clone();
+    tokio::spawn(async move { indexer_state.indexer_client.run().await });
+
+    //periodically queue the pages that are due for a recrawl
+    if state.config.recrawl {
+        let state = state.clone();
         tokio::spawn(async move {
-            let (content, crawled_urls) = match crawl_url(&http_client, url.as_str()).await {
-                Err(e) => {
-                    log::debug!("Error crawling ({}): {}", url, e);
-                    return;
+            loop {
+                tokio::time::delay_for(RECRAWL_CHECK_INTERVAL).await;
+                let due = state.recrawl_schedule.take_due();
+                if !due.is_empty() {
+                    log::info!("Queueing {} pages to recrawl", due.len());
                 }
-                Ok(result) => result,
-            };
+                for (url, depth) in due {
+                    //the frontier is full or already has it
+                    if !state.frontier.recrawl(&url, depth) {
+                        state.recrawl_schedule.postpone(&url);
+                    }
+                }
+            }
+        });
+    }
 
-            //DONT FORGET ENUMS
-            //CAN WE DO UNWRAP OR RETURN or lambda
-            //HOW DOES CRAWLER WORK. DOESNT QUEUE FILL. LOTS OF WAITING THINGS??
-
-            //log::debug!("Content: {:?}", &content);
-            log::debug!("Next urls: {:?}", &crawled_urls);
-
-            //push content to index
-            let indexer_response = match push_crawl_entry_to_indexer(
-                &http_client,
-                "http://127.0.0.1:4444/api/resource".to_string(),
-                url,
-                content,
-            )
-            .await
-            {
-                Err(e) => {
-                    log::debug!("{e}");
-                    return;
+    //and the ones that are due for another attempt after failing
+    let retry_state = state.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::delay_for(RETRY_CHECK_INTERVAL).await;
+            for (url, depth) in retry_state.retries.take_due() {
+                if !retry_state.frontier.recrawl(&url, depth) {
+                    retry_state.retries.postpone(&url);
                 }
-                Ok(res) => res.text().await,
-            };
+            }
+        }
+    });
 
-            log::debug!("Pushed to indexer {:?}", &indexer_response);
+    //caps the number of urls being crawled at the same time
+    let tasks = Arc::new(Semaphore::new(state.config.concurrency));
 
-            for url in crawled_urls {
-                tx_crawling_queue.send(url).await.unwrap();
+    //and start crawling
+    loop {
+        //we dont crawl more than the indexer can take
+        state.indexer_client.ready().await;
+        //blocks - we move it up here as to at least block for next url and not endesly spawn tasks
+        let entry = state.frontier.pop().await;
+        let task_permit = tasks.clone().acquire_owned().await;
+        let state = state.clone();
+        tokio::spawn(async move {
+            //urls that will be retried stay in the frontier's journal, so they arent lost on a restart
+            if crawl_and_index(&state, &entry).await {
+                state.frontier.complete(&entry.url);
             }
+            drop(task_permit);
         });
     }
 }
 
-async fn crawl_url(http_client: &Client, url: &str) -> Result<(String, Vec<String>), String> {
+//returns whether we are done with the url, false if it failed and will be retried later
+async fn crawl_and_index(state: &Arc<CrawlerState>, entry: &FrontierEntry) -> bool {
+    let url = entry.url.as_str();
+    let parsed_url = match Url::parse(url) {
+        Err(e) => {
+            log::debug!("Invalid url ({}): {}", url, e);
+            return true;
+        }
+        Ok(parsed_url) => parsed_url,
+    };
+    let host = parsed_url.host_str().unwrap_or_default().to_string();
+
+    let robots = match state
+        .robots_cache
+        .get(&state.http_client, &state.host_scheduler, &parsed_url)
+        .await
+    {
+        Err(e) => {
+            log::debug!("Error crawling ({}): {}", url, e);
+            return !state.retries.failed(url, entry.depth, &e);
+        }
+        Ok(robots) => robots,
+    };
+
+    let origin = parsed_url.origin().ascii_serialization();
+    if state.config.sitemaps && state.sitemap_origins.lock().unwrap().insert(origin.clone()) {
+        //reading them can take a while, the url shouldnt wait for it
+        let (state, robots) = (state.clone(), robots.clone());
+        tokio::spawn(async move { queue_sitemap_urls(&state, &origin, &robots).await });
+    }
+
+    if !robots.is_allowed(&parsed_url) {
+        log::info!("Skipping {} (disallowed by robots.txt)", url);
+        return true;
+    }
+
+    let host_permit = state
+        .host_scheduler
+        .acquire(&host, robots.crawl_delay)
+        .await;
+    let previous = state.recrawl_schedule.page(url);
+    let validators = previous.as_ref().map(|p| &p.validators);
+    let crawl_result = crawl_url(state, url, validators, host_permit).await;
+
+    let page = match crawl_result {
+        Err(e) => {
+            log::debug!("Error crawling ({}): {}", url, e);
+            //the server wants us to slow down
+            if let Some(retry_after) = e.retry_after() {
+                state.host_scheduler.back_off(&host, retry_after);
+            }
+            let will_retry = state.retries.failed(url, entry.depth, &e);
+            //we keep checking on pages we crawled before, just less often
+            if let (false, Some(previous)) = (will_retry, previous) {
+                state.recrawl_schedule.record(
+                    url,
+                    entry.depth,
+                    previous.validators,
+                    previous.content_hash,
+                    false,
+                );
+            }
+            return !will_retry;
+        }
+        Ok(CrawlOutcome::NotModified) => {
+            log::debug!("Not modified: {}", url);
+            if let Some(previous) = previous {
+                state.recrawl_schedule.record(
+                    url,
+                    entry.depth,
+                    previous.validators,
+                    previous.content_hash,
+                    false,
+                );
+            }
+            state.retries.succeeded(url);
+            return true;
+        }
+        Ok(CrawlOutcome::Crawled(page)) => page,
+    };
+
+    //some servers dont support conditional requests, so we compare the content too
+    let content_hash = recrawl::content_hash(&page.content);
+    let changed = match previous {
+        None => true,
+        Some(previous) => previous.content_hash != content_hash,
+    };
+    if !changed {
+        log::debug!("Unchanged content: {}", url);
+        state
+            .recrawl_schedule
+            .record(url, entry.depth, page.validators, content_hash, false);
+        state.retries.succeeded(url);
+        return true;
+    }
+    let (content, filetype, crawled_urls, links, document, noindex, metadata) = (
+        page.content,
+        page.filetype,
+        page.next_urls,
+        page.links,
+        page.document,
+        page.noindex,
+        page.metadata,
+    );
+
+    //log::debug!("Content: {:?}", &content);
+    log::debug!("Next urls: {:?}", &crawled_urls);
+
+    //the content is indexed under the url it came from, the ones that redirected to it are aliases
+    let final_url = metadata.final_url.clone();
+    let redirect_chain: Vec<String> = metadata
+        .redirects
+        .iter()
+        .chain(std::iter::once(&final_url))
+        .filter(|alias| alias.as_str() != url)
+        .cloned()
+        .collect();
+
+    //push content to index
+    let resource = match noindex {
+        //we only tell the indexer to forget it, in case it had it from before
+        true => {
+            log::debug!("Not indexing {} (noindex)", url);
+            CrawledResource {
+                url: final_url,
+                content: String::new(),
+                filetype: filetype.to_string(),
+                links: vec![],
+                document: None,
+                noindex: true,
+                metadata: Some(metadata),
+            }
+        }
+        false => CrawledResource {
+            url: final_url,
+            //no need for the raw html if we send the extracted document
+            content: match document {
+                None => content,
+                Some(_) => String::new(),
+            },
+            filetype: filetype.to_string(),
+            links,
+            document,
+            noindex: false,
+            metadata: Some(metadata),
+        },
+    };
+    match state.indexer_client.push(resource) {
+        Err(e) => {
+            log::error!("{}", e);
+            let will_retry = state
+                .retries
+                .failed(url, entry.depth, &CrawlError::Indexer(e));
+            if !will_retry {
+                //forget the validators and hash so the next visit fetches and pushes it again
+                state
+                    .recrawl_schedule
+                    .record(url, entry.depth, Validators::default(), 0, true);
+            }
+            return !will_retry;
+        }
+        Ok(()) => log::debug!("Queued {} for the indexer", url),
+    }
+    state
+        .recrawl_schedule
+        .record(url, entry.depth, page.validators, content_hash, true);
+    state.retries.succeeded(url);
+
+    //we already have them, no need to crawl them on their own
+    for alias in redirect_chain {
+        state.frontier.complete_alias(&alias);
+    }
+    for url in crawled_urls {
+        state.frontier.push(&url, entry.depth + 1);
+    }
+    true
+}
+
+struct CrawledPage {
+    content: String,
+    filetype: &'static str,
+    next_urls: Vec<String>,
+    links: Vec<CrawledLink>,
+    document: Option<ExtractedDocument>,
+    noindex: bool,
+    metadata: CrawlMetadata,
+    validators: Validators,
+}
+
+enum CrawlOutcome {
+    NotModified,
+    Crawled(Box<CrawledPage>),
+}
+
+async fn crawl_url(
+    state: &CrawlerState,
+    url: &str,
+    validators: Option<&Validators>,
+    //of the url's host, we take the permit of each host we are redirected to
+    mut host_permit: OwnedSemaphorePermit,
+) -> Result<CrawlOutcome, CrawlError> {
     log::debug!("Crawling {:?}", url);
 
     let url = Url::parse(url).unwrap();
+    let config = &state.config;
+
+    let fetched_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    //we follow the redirects ourselves so we know where the content really came from
+    let mut final_url = url.clone();
+    let mut redirects = vec![];
+    let response = loop {
+        //if we crawled it before we only want it back if it changed (the validators are the url's,
+        //not the ones of the urls it redirects to)
+        let validators = validators.filter(|_| redirects.is_empty());
+        let mut request = state.page_client.get(final_url.as_str());
+        if let Some(etag) = validators.and_then(|v| v.etag.as_ref()) {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = validators.and_then(|v| v.last_modified.as_ref()) {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = request.send().await?;
+
+        let location = match response.status() {
+            StatusCode::NOT_MODIFIED => None,
+            status if status.is_redirection() => response
+                .headers()
+                .get(LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .and_then(|l| final_url.join(l).ok()),
+            _ => None,
+        };
+        let next_url = match location {
+            None => break response,
+            Some(next_url) => next_url,
+        };
 
-    let response_text = match http_client.get(url.as_str()).send().await {
-        Ok(text_res) if text_res.status() == StatusCode::OK => match text_res.text().await {
-            Err(_) => {
-                Err("Error unwrapping the fetched HTML's text (".to_owned() + url.as_str() + ")")
+        redirects.push(final_url.to_string());
+        if redirects.len() > config.max_redirects || redirects.contains(&next_url.to_string()) {
+            return Err(CrawlError::TooManyRedirects);
+        }
+        //redirects can take us to urls with queries and the like, we only care about the scope and robots.txt
+        if !(next_url.scheme() == "http" || next_url.scheme() == "https")
+            || !state.scope.contains(&next_url)
+        {
+            return Err(CrawlError::Redirect(next_url.to_string()));
+        }
+        //the next host gets the same politeness as any other (and it may be the same host)
+        drop(host_permit);
+        let robots = state
+            .robots_cache
+            .get(&state.http_client, &state.host_scheduler, &next_url)
+            .await?;
+        if !robots.is_allowed(&next_url) {
+            return Err(CrawlError::Redirect(next_url.to_string()));
+        }
+        log::debug!("{} redirects to {}", final_url, next_url);
+
+        host_permit = state
+            .host_scheduler
+            .acquire(next_url.host_str().unwrap_or_default(), robots.crawl_delay)
+            .await;
+        final_url = next_url;
+    };
+    let headers = response.headers().clone();
+    match response.status() {
+        StatusCode::OK => {}
+        StatusCode::NOT_MODIFIED => return Ok(CrawlOutcome::NotModified),
+        status => return Err(CrawlError::from_status(status, &headers)),
+    }
+
+    //no point in downloading what we cant index
+    let content_type = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    let filetype = match content_type {
+        None => None,
+        Some(content_type) => {
+            match documents::filetype_from_mime(content_type.split(';').next().unwrap_or("")) {
+                None => return Err(CrawlError::UnsupportedContentType(content_type.to_string())),
+                filetype => filetype,
             }
-            Ok(text) => Ok(text),
-        },
+        }
+    };
 
-        _ => Err("Error fetching ".to_owned() + url.as_str()),
-    }?;
+    let body = fetch::read_body(response, config.max_response_size).await?;
+    let filetype = match filetype {
+        None => documents::sniff_filetype(&body)?,
+        Some(filetype) => filetype,
+    };
+    let validators = validators_from(&headers);
+    let mut robots = robots::x_robots_tag(&headers, &config.user_agent);
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    let metadata = CrawlMetadata {
+        fetched_at,
+        status: StatusCode::OK.as_u16(),
+        final_url: final_url.to_string(),
+        redirects,
+        content_type: header(CONTENT_TYPE),
+        last_modified: header(LAST_MODIFIED),
+        content_language: header(CONTENT_LANGUAGE),
+        size: body.len(),
+    };
 
-    let document = scraper::Html::parse_document(response_text.as_str());
+    //only html pages have links to follow
+    let (content, next_urls, links, document) = match filetype {
+        FILETYPE_PDF => (documents::pdf_to_text(body).await?, vec![], vec![], None),
+        FILETYPE_TEXT => (
+            fetch::decode_body(&body, &headers, &final_url, false),
+            vec![],
+            vec![],
+            None,
+        ),
+        _ => {
+            let content = fetch::decode_body(&body, &headers, &final_url, true);
+            let mut document = extraction::extract_html(&content, Some(&final_url));
+            robots = robots.or(document.robots);
+            let (next_urls, links) = match robots.nofollow {
+                true => (vec![], vec![]),
+                false => crawlable_links(state, &final_url, &document.links),
+            };
+            match config.extract_documents {
+                true => {
+                    document.links = links;
+                    (content, next_urls, vec![], Some(document))
+                }
+                false => (content, next_urls, links, None),
+            }
+        }
+    };
 
-    let valid_url = |check_url: &Url| match check_url {
-        u if !(u.scheme() == "http" || u.scheme() == "https") => false,
-        u if u.fragment().is_some() => false, //no # urls
-        u if u.query().is_some() => false,    //no ? urls
-        u if u.path_segments().is_some() && u.path_segments().unwrap().count() > 4 => false, // max "crawling depth" is 4
-        u if *u == url => false, //no same url
-        _ => true,
+    log::debug!("Returning next urls, {:?}", next_urls);
+    Ok(CrawlOutcome::Crawled(Box::new(CrawledPage {
+        content,
+        filetype,
+        next_urls,
+        links,
+        document,
+        noindex: robots.noindex,
+        metadata,
+        validators,
+    })))
+}
+
+//the urls worth crawling next (the frontier decides which ones first) and the links whose anchor text we index
+fn crawlable_links(
+    state: &CrawlerState,
+    url: &Url,
+    links: &[CrawledLink],
+) -> (Vec<String>, Vec<CrawledLink>) {
+    let valid_url = |check_url: &str| match Url::parse(check_url) {
+        Ok(u) => &u != url && is_crawlable(state, &u), //no same url
+        Err(_) => false,
     };
 
-    let link_selector = scraper::Selector::parse("a").unwrap();
-    let next_urls = document
-        .select(&link_selector)
-        .filter_map(|link| link.value().attr("href"))
+    let next_urls = links
+        .iter()
+        .filter(|link| !link.nofollow)
+        .map(|link| link.url.as_str())
         .unique()
-        .map(|u| url.join(u))
-        .filter(Result::is_ok)
-        .map(Result::unwrap)
-        .filter(valid_url)
+        .filter(|u| valid_url(u))
+        .take(state.config.links_per_page)
         .map(String::from)
-        .choose_multiple(&mut rand::thread_rng(), 2); //we shuffle as to minimise repeating links
+        .collect::<Vec<String>>();
+
+    let links = links
+        .iter()
+        .filter(|link| !link.nofollow && !link.anchor_text.is_empty() && valid_url(&link.url))
+        .cloned()
+        .collect();
 
     //normalise words somewhere
     //fuzzy? - iterate over keys
@@ -124,32 +639,96 @@ async fn crawl_url(http_client: &Client, url: &str) -> Result<(String, Vec<Strin
     //search for phrases?
     //http workings lagging behind crawler, what to do?
     //i dont understand dbg! (how to print {})
-    //user agent?
 
-    log::debug!("Returning next urls, {:?}", next_urls);
-    Ok((response_text, next_urls))
+    (next_urls, links)
 }
 
-async fn push_crawl_entry_to_indexer(
-    http_client: &Client,
-    indexer_url: String,
-    url: String,
-    content: String,
-) -> Result<Response, String> {
-    log::debug!("Pushin to indexer");
+fn validators_from(headers: &HeaderMap) -> Validators {
+    //tabs would break the pages journal, no sane server sends them anyway
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty() && !v.contains('\t'))
+            .map(String::from)
+    };
 
-    let request_body = CrawledResource { url, content };
+    Validators {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    }
+}
 
-    match http_client
-        .post(&indexer_url)
-        .json(&request_body)
-        .send()
-        .await
-    {
-        Err(_) => Err(format!(
-            "Error pushing the crawler to indexer! {:?}",
-            &indexer_url
-        )),
-        Ok(response) => Ok(response),
+fn is_crawlable(state: &CrawlerState, url: &Url) -> bool {
+    match url {
+        u if !(u.scheme() == "http" || u.scheme() == "https") => false,
+        u if u.fragment().is_some() => false, //no # urls
+        u if u.query().is_some() => false,    //no ? urls
+        u if u.path_segments().is_some()
+            && u.path_segments().unwrap().count() > state.config.max_depth =>
+        {
+            false
+        } // max "crawling depth"
+        u if !state.scope.contains(u) => false,
+        _ => true,
+    }
+}
+
+//reads the sitemaps listed in the robots.txt and /sitemap.xml, following sitemap indexes,
+//and queues their urls with the priority and last modification hints they give us
+async fn queue_sitemap_urls(state: &CrawlerState, origin: &str, robots: &RobotsTxt) {
+    let mut pending = robots.sitemaps.clone();
+    let default_sitemap = format!("{origin}/sitemap.xml");
+    if !pending.contains(&default_sitemap) {
+        pending.insert(0, default_sitemap);
+    }
+
+    let mut fetched = HashSet::new();
+    let mut queued = 0;
+    while let Some(sitemap_url) = pending.pop() {
+        if fetched.len() >= MAX_SITEMAPS_PER_SITE {
+            break;
+        }
+        let parsed_url = match Url::parse(&sitemap_url) {
+            Ok(u) if state.scope.contains(&u) && robots.is_allowed(&u) => u,
+            _ => continue,
+        };
+        if !fetched.insert(sitemap_url.clone()) {
+            continue;
+        }
+
+        let host = parsed_url.host_str().unwrap_or_default();
+        let host_permit = state.host_scheduler.acquire(host, robots.crawl_delay).await;
+        let sitemap = sitemap::fetch(
+            &state.http_client,
+            &sitemap_url,
+            state.config.max_response_size,
+        )
+        .await;
+        drop(host_permit);
+
+        match sitemap {
+            Err(e) => log::debug!("{}", e),
+            Ok(Sitemap::Index(sitemaps)) => pending.extend(sitemaps),
+            Ok(Sitemap::UrlSet(urls)) => {
+                for url in urls {
+                    match Url::parse(&url.loc) {
+                        Ok(u) if is_crawlable(state, &u) => {}
+                        _ => continue,
+                    }
+                    //sitemaps list the pages of the site, so they are one step away from its root
+                    if state
+                        .frontier
+                        .push_from_sitemap(&url.loc, 1, url.priority, url.modified_at)
+                    {
+                        queued += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    if queued > 0 {
+        log::info!("Queued {} urls from the sitemaps of {}", queued, origin);
     }
 }
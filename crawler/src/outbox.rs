@@ -0,0 +1,155 @@
+use lib::lib::CrawledResource;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const OUTBOX_DIRECTORY: &str = "outbox";
+//resources the indexer refused, kept around so they can be looked into
+const REJECTED_DIRECTORY: &str = "rejected";
+
+struct OutboxState {
+    //ids of the resources waiting to be delivered, oldest first
+    queue: VecDeque<u64>,
+    next_id: u64,
+}
+
+//resources crawled but not yet delivered to the indexer, spooled to disk (one "<id>.json" file each)
+//so they survive the indexer being down and crawler restarts. only the ids are kept in memory.
+pub struct Outbox {
+    directory: PathBuf,
+    state: Mutex<OutboxState>,
+}
+
+impl Outbox {
+    pub fn open(directory: &Path) -> std::io::Result<Self> {
+        let directory = directory.join(OUTBOX_DIRECTORY);
+        std::fs::create_dir_all(directory.join(REJECTED_DIRECTORY))?;
+
+        let mut ids = vec![];
+        for entry in std::fs::read_dir(&directory)? {
+            let path = entry?.path();
+            match path.extension().and_then(|e| e.to_str()) {
+                //half written resources from a crash
+                Some("tmp") => std::fs::remove_file(&path)?,
+                Some("json") => match path.file_stem().and_then(|s| s.to_str()?.parse().ok()) {
+                    Some(id) => ids.push(id),
+                    None => log::warn!("Ignoring unknown outbox file {}", path.display()),
+                },
+                _ => {}
+            }
+        }
+        ids.sort_unstable();
+        if !ids.is_empty() {
+            log::info!("{} resources waiting in the outbox", ids.len());
+        }
+
+        Ok(Self {
+            directory,
+            state: Mutex::new(OutboxState {
+                next_id: ids.last().map_or(0, |id| id + 1),
+                queue: ids.into(),
+            }),
+        })
+    }
+
+    //spools the resource, returns how many resources are waiting
+    pub fn push(&self, resource: &CrawledResource) -> std::io::Result<usize> {
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            state.next_id += 1;
+            state.next_id - 1
+        };
+
+        //written to a temporary file first so we never deliver half a resource
+        let tmp_path = self.directory.join(format!("{id}.tmp"));
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, resource)?;
+        //and only renamed once it is safely on disk, so a crash cant leave an empty one behind
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        std::fs::rename(&tmp_path, self.path(id))?;
+
+        let mut state = self.state.lock().unwrap();
+        //ids are taken in order but written concurrently, keep the queue sorted
+        let position = state.queue.partition_point(|queued| *queued < id);
+        state.queue.insert(position, id);
+        Ok(state.queue.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+
+    //the oldest resources waiting to be delivered, they stay in the outbox until they are removed
+    pub fn peek(&self, max: usize) -> Vec<(u64, CrawledResource)> {
+        let ids: Vec<u64> = {
+            let state = self.state.lock().unwrap();
+            state.queue.iter().take(max).copied().collect()
+        };
+
+        let mut resources = vec![];
+        for id in ids {
+            let resource = File::open(self.path(id))
+                .map_err(|e| e.to_string())
+                .and_then(|f| {
+                    serde_json::from_reader(BufReader::new(f)).map_err(|e| e.to_string())
+                });
+            match resource {
+                Ok(resource) => resources.push((id, resource)),
+                Err(e) => {
+                    log::error!("Dropping unreadable outbox file {}: {}", id, e);
+                    self.reject(&[id]);
+                }
+            }
+        }
+        resources
+    }
+
+    //the resources got to the indexer
+    pub fn remove(&self, ids: &[u64]) {
+        self.forget(ids);
+        for id in ids {
+            if let Err(e) = std::fs::remove_file(self.path(*id)) {
+                log::error!("Error removing outbox file {}: {}", id, e);
+            }
+        }
+    }
+
+    //the resources will never get to the indexer, they are moved to the rejected directory
+    pub fn reject(&self, ids: &[u64]) {
+        self.forget(ids);
+        for id in ids {
+            //ids start over once the outbox is empty, so we add when it was rejected
+            let rejected_path = self
+                .directory
+                .join(REJECTED_DIRECTORY)
+                .join(format!("{}-{id}.json", now()));
+            if let Err(e) = std::fs::rename(self.path(*id), rejected_path) {
+                log::error!("Error moving outbox file {}: {}", id, e);
+            }
+        }
+    }
+
+    fn forget(&self, ids: &[u64]) {
+        self.state
+            .lock()
+            .unwrap()
+            .queue
+            .retain(|queued| !ids.contains(queued));
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.directory.join(format!("{id}.json"))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
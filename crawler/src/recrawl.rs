@@ -0,0 +1,217 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const JOURNAL_FILE: &str = "pages.log";
+
+//what we need to ask the server whether a page changed since we last fetched it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PageState {
+    pub depth: u32,
+    pub validators: Validators,
+    pub content_hash: u64,
+    interval_secs: u64,
+    next_crawl_at: u64,
+}
+
+struct ScheduleState {
+    pages: HashMap<String, PageState>,
+    //ordered by when each page is due, the first one is the next to recrawl
+    timeline: BTreeSet<(u64, String)>,
+    journal: File,
+}
+
+//remembers the pages we crawled and decides when to revisit them. pages that change often are
+//recrawled more frequently: the interval is halved when a page changed and doubled when it didnt.
+//every crawl appends a "url\tdepth\tinterval\tnext_crawl_at\thash\tetag\tlast_modified" line to a journal.
+pub struct RecrawlSchedule {
+    state: Mutex<ScheduleState>,
+    min_interval: Duration,
+    initial_interval: Duration,
+    max_interval: Duration,
+}
+
+impl RecrawlSchedule {
+    pub fn open(
+        directory: &Path,
+        min_interval: Duration,
+        initial_interval: Duration,
+        max_interval: Duration,
+    ) -> std::io::Result<Self> {
+        std::fs::create_dir_all(directory)?;
+        let journal_path = directory.join(JOURNAL_FILE);
+
+        let mut pages = HashMap::new();
+        if journal_path.exists() {
+            for line in BufReader::new(File::open(&journal_path)?).lines() {
+                let line = line?;
+                match parse_line(&line) {
+                    None => log::warn!("Ignoring invalid pages journal line: {:?}", line),
+                    Some((url, page)) => _ = pages.insert(url, page),
+                }
+            }
+        }
+        log::info!("Loaded the recrawl schedule of {} pages", pages.len());
+
+        //compact the journal so it only has the last state of each page
+        let tmp_path = PathBuf::from(format!("{}.tmp", journal_path.display()));
+        let mut compacted = BufWriter::new(File::create(&tmp_path)?);
+        for (url, page) in &pages {
+            writeln!(compacted, "{}", format_line(url, page))?;
+        }
+        compacted.flush()?;
+        drop(compacted);
+        std::fs::rename(&tmp_path, &journal_path)?;
+
+        let timeline = pages
+            .iter()
+            .map(|(url, page)| (page.next_crawl_at, url.clone()))
+            .collect();
+
+        Ok(Self {
+            state: Mutex::new(ScheduleState {
+                pages,
+                timeline,
+                journal: OpenOptions::new().append(true).open(&journal_path)?,
+            }),
+            min_interval,
+            initial_interval,
+            max_interval,
+        })
+    }
+
+    pub fn page(&self, url: &str) -> Option<PageState> {
+        self.state.lock().unwrap().pages.get(url).cloned()
+    }
+
+    //records a crawl of the page and schedules the next one
+    pub fn record(
+        &self,
+        url: &str,
+        depth: u32,
+        validators: Validators,
+        content_hash: u64,
+        changed: bool,
+    ) {
+        let mut state = self.state.lock().unwrap();
+
+        let interval_secs = match state.pages.get(url) {
+            None => self.initial_interval.as_secs(),
+            Some(previous) if changed => previous.interval_secs / 2,
+            Some(previous) => previous.interval_secs.saturating_mul(2),
+        }
+        .clamp(self.min_interval.as_secs(), self.max_interval.as_secs());
+
+        let page = PageState {
+            depth,
+            validators,
+            content_hash,
+            interval_secs,
+            next_crawl_at: now() + interval_secs,
+        };
+
+        if let Err(e) = writeln!(state.journal, "{}", format_line(url, &page)) {
+            log::error!("Error writing to the pages journal: {}", e);
+        }
+        if let Some(previous) = state.pages.insert(url.to_string(), page.clone()) {
+            state
+                .timeline
+                .remove(&(previous.next_crawl_at, url.to_string()));
+        }
+        state.timeline.insert((page.next_crawl_at, url.to_string()));
+    }
+
+    //the page couldnt be queued for its recrawl, we try again after the min interval
+    pub fn postpone(&self, url: &str) {
+        let mut state = self.state.lock().unwrap();
+        let page = match state.pages.get_mut(url) {
+            None => return,
+            Some(page) => page,
+        };
+        let previous_crawl_at = page.next_crawl_at;
+        page.next_crawl_at = now() + self.min_interval.as_secs();
+        let line = format_line(url, page);
+        let next_crawl_at = page.next_crawl_at;
+
+        if let Err(e) = writeln!(state.journal, "{line}") {
+            log::error!("Error writing to the pages journal: {}", e);
+        }
+        state.timeline.remove(&(previous_crawl_at, url.to_string()));
+        state.timeline.insert((next_crawl_at, url.to_string()));
+    }
+
+    //takes the pages that are due for a recrawl (they are scheduled again once they are recorded)
+    pub fn take_due(&self) -> Vec<(String, u32)> {
+        let mut state = self.state.lock().unwrap();
+        let now = now();
+
+        let mut due = vec![];
+        while let Some((next_crawl_at, url)) = state.timeline.iter().next().cloned() {
+            if next_crawl_at > now {
+                break;
+            }
+            state.timeline.remove(&(next_crawl_at, url.clone()));
+            let depth = state.pages[&url].depth;
+            due.push((url, depth));
+        }
+        due
+    }
+}
+
+fn format_line(url: &str, page: &PageState) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        url,
+        page.depth,
+        page.interval_secs,
+        page.next_crawl_at,
+        page.content_hash,
+        page.validators.etag.as_deref().unwrap_or_default(),
+        page.validators.last_modified.as_deref().unwrap_or_default()
+    )
+}
+
+fn parse_line(line: &str) -> Option<(String, PageState)> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    match fields.as_slice() {
+        [url, depth, interval_secs, next_crawl_at, content_hash, etag, last_modified] => {
+            let non_empty = |s: &str| Some(s.to_string()).filter(|s| !s.is_empty());
+            Some((
+                url.to_string(),
+                PageState {
+                    depth: depth.parse().ok()?,
+                    validators: Validators {
+                        etag: non_empty(etag),
+                        last_modified: non_empty(last_modified),
+                    },
+                    content_hash: content_hash.parse().ok()?,
+                    interval_secs: interval_secs.parse().ok()?,
+                    next_crawl_at: next_crawl_at.parse().ok()?,
+                },
+            ))
+        }
+        _ => None,
+    }
+}
+
+//FNV-1a, unlike std's hasher it is guaranteed to be stable so we can persist it
+pub fn content_hash(content: &str) -> u64 {
+    content.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
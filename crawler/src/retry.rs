@@ -0,0 +1,126 @@
+use crate::error::CrawlError;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+
+const DEAD_LETTERS_FILE: &str = "dead_letters.log";
+
+//we never wait longer than this between attempts, whatever Retry-After says
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60 * 24);
+
+struct RetryState {
+    //failed attempts of the urls being retried
+    attempts: HashMap<String, (u32, u32)>, //url -> (attempts, depth)
+    //ordered by when each url should be retried
+    timeline: BTreeSet<(Instant, String)>,
+}
+
+//retries the urls that failed for transient reasons with exponential backoff (or when the server
+//tells us to with Retry-After). urls that fail permanently, or too many times, are given up on and
+//appended to a "unix_time\turl\terror" dead letters file so they can be looked into.
+pub struct Retries {
+    state: Mutex<RetryState>,
+    dead_letters: Mutex<File>,
+    max_retries: u32,
+    base_delay: Duration,
+}
+
+impl Retries {
+    pub fn open(directory: &Path, max_retries: u32, base_delay: Duration) -> std::io::Result<Self> {
+        std::fs::create_dir_all(directory)?;
+        let dead_letters = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(directory.join(DEAD_LETTERS_FILE))?;
+
+        Ok(Self {
+            state: Mutex::new(RetryState {
+                attempts: HashMap::new(),
+                timeline: BTreeSet::new(),
+            }),
+            dead_letters: Mutex::new(dead_letters),
+            max_retries,
+            base_delay,
+        })
+    }
+
+    //records a failed attempt, returns whether the url will be retried
+    pub fn failed(&self, url: &str, depth: u32, error: &CrawlError) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let attempts = state.attempts.get(url).map_or(0, |(a, _)| *a) + 1;
+
+        if !error.is_transient() || attempts > self.max_retries {
+            state.attempts.remove(url);
+            drop(state);
+            log::info!(
+                "Giving up on {} after {} attempts: {}",
+                url,
+                attempts,
+                error
+            );
+            self.dead_letter(url, error);
+            return false;
+        }
+
+        let delay = error
+            .retry_after()
+            .unwrap_or_else(|| self.base_delay * 2u32.saturating_pow(attempts - 1))
+            .min(MAX_RETRY_DELAY);
+        log::debug!("Retrying {} in {:?} ({})", url, delay, error);
+
+        state.attempts.insert(url.to_string(), (attempts, depth));
+        state
+            .timeline
+            .insert((Instant::now() + delay, url.to_string()));
+        true
+    }
+
+    pub fn succeeded(&self, url: &str) {
+        self.state.lock().unwrap().attempts.remove(url);
+    }
+
+    //the url couldnt be queued for its next attempt, we try again after the base delay
+    pub fn postpone(&self, url: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.attempts.contains_key(url) {
+            state
+                .timeline
+                .insert((Instant::now() + self.base_delay, url.to_string()));
+        }
+    }
+
+    //takes the urls that are due for another attempt
+    pub fn take_due(&self) -> Vec<(String, u32)> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        let mut due = vec![];
+        while let Some((retry_at, url)) = state.timeline.iter().next().cloned() {
+            if retry_at > now {
+                break;
+            }
+            state.timeline.remove(&(retry_at, url.clone()));
+            if let Some((_, depth)) = state.attempts.get(&url) {
+                due.push((url, *depth));
+            }
+        }
+        due
+    }
+
+    fn dead_letter(&self, url: &str, error: &CrawlError) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        //the error could have anything in it, but we want one line per url
+        let error = error.to_string().replace(['\t', '\n', '\r'], " ");
+
+        let mut dead_letters = self.dead_letters.lock().unwrap();
+        if let Err(e) = writeln!(dead_letters, "{now}\t{url}\t{error}") {
+            log::error!("Error writing to the dead letters file: {}", e);
+        }
+    }
+}
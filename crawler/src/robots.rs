@@ -0,0 +1,475 @@
+use crate::error::CrawlError;
+use crate::fetch;
+use crate::scheduler::HostScheduler;
+use lib::extraction;
+use lib::lib::RobotsDirectives;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+//robots.txt files shouldnt be cached for longer than a day (RFC 9309)
+const ROBOTS_TXT_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+//but when we cant get one we try again soon, its urls are retried meanwhile
+const ROBOTS_TXT_FAILURE_TTL: Duration = Duration::from_secs(60);
+//above this many cached robots.txt files we forget the expired ones, then the oldest
+const MAX_CACHED_ROBOTS_TXTS: usize = 10000;
+//we dont read more than this, same as the RFC's minimum
+const MAX_ROBOTS_TXT_SIZE: usize = 500 * 1024;
+
+#[derive(Debug, Clone)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+//the rules of a robots.txt that apply to our user agent
+#[derive(Debug, Clone, Default)]
+pub struct RobotsTxt {
+    rules: Vec<Rule>,
+    pub crawl_delay: Option<Duration>,
+    //sitemap urls, they dont belong to any group
+    pub sitemaps: Vec<String>,
+}
+
+impl RobotsTxt {
+    pub fn parse(content: &str, user_agent: &str) -> Self {
+        let user_agent = user_agent.to_lowercase();
+
+        //rules of the groups that name our user agent and of the "*" groups
+        let mut specific = RobotsTxt::default();
+        let mut wildcard = RobotsTxt::default();
+        let mut found_specific = false;
+        let mut sitemaps = vec![];
+
+        //user agents of the group we are currently in
+        let mut group_agents: Vec<String> = vec![];
+        let mut in_rules = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let (key, value) = match line.split_once(':') {
+                None => continue,
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            };
+
+            if key == "sitemap" {
+                if !value.is_empty() {
+                    sitemaps.push(value.to_string());
+                }
+                continue;
+            }
+
+            if key == "user-agent" {
+                //a user-agent line after some rules starts a new group
+                if in_rules {
+                    group_agents.clear();
+                    in_rules = false;
+                }
+                group_agents.push(value.to_lowercase());
+                continue;
+            }
+
+            if !matches!(key.as_str(), "allow" | "disallow" | "crawl-delay") {
+                continue;
+            }
+            in_rules = true;
+
+            //the product token has to match exactly (case insensitively, RFC 9309)
+            let is_specific = group_agents.contains(&user_agent);
+            let target = if is_specific {
+                found_specific = true;
+                &mut specific
+            } else if group_agents.iter().any(|a| a == "*") {
+                &mut wildcard
+            } else {
+                continue;
+            };
+
+            match key.as_str() {
+                "crawl-delay" => {
+                    target.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|d| d.is_finite() && *d >= 0.0)
+                        .map(Duration::from_secs_f64)
+                }
+                //an empty disallow means everything is allowed
+                "disallow" if value.is_empty() => {}
+                rule => target.rules.push(Rule {
+                    allow: rule == "allow",
+                    pattern: value.to_string(),
+                }),
+            }
+        }
+
+        let mut robots = if found_specific { specific } else { wildcard };
+        robots.sitemaps = sitemaps;
+        robots
+    }
+
+    //the most specific (longest) matching rule wins, allow wins on ties
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            None => url.path().to_string(),
+            Some(query) => format!("{}?{}", url.path(), query),
+        };
+
+        match self
+            .rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, &path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+        {
+            None => true,
+            Some(rule) => rule.allow,
+        }
+    }
+}
+
+//robots.txt patterns match path prefixes, "*" matches any sequence and "$" anchors the end
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        None => (pattern, false),
+        Some(pattern) => (pattern, true),
+    };
+    let pattern = pattern.as_bytes();
+    let path = path.as_bytes();
+
+    //possible[j] is true if the pattern read so far can match the first j bytes of the path
+    let mut possible = vec![false; path.len() + 1];
+    possible[0] = true;
+    for c in pattern {
+        if *c == b'*' {
+            for j in 1..=path.len() {
+                possible[j] = possible[j] || possible[j - 1];
+            }
+        } else {
+            for j in (1..=path.len()).rev() {
+                possible[j] = possible[j - 1] && path[j - 1] == *c;
+            }
+            possible[0] = false;
+        }
+    }
+
+    if anchored {
+        possible[path.len()]
+    } else {
+        possible.iter().any(|p| *p)
+    }
+}
+
+//the X-Robots-Tag headers of a response, e.g. "noindex" or "somebot: noindex, nofollow"
+//(the ones for other bots are ignored)
+pub fn x_robots_tag(headers: &HeaderMap, user_agent: &str) -> RobotsDirectives {
+    headers
+        .get_all("x-robots-tag")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|value| match value.split_once(':') {
+            //"unavailable_after: <date>" is a directive, not a bot
+            Some((bot, directives))
+                if !bot.contains(',') && !bot.trim().eq_ignore_ascii_case("unavailable_after") =>
+            {
+                Some(directives).filter(|_| bot.trim().eq_ignore_ascii_case(user_agent))
+            }
+            _ => Some(value),
+        })
+        .map(extraction::robots_directives)
+        .fold(RobotsDirectives::default(), RobotsDirectives::or)
+}
+
+//the robots.txt, or why we couldnt get it
+type CachedRobotsTxt = Result<Arc<RobotsTxt>, String>;
+
+//fetches and caches the robots.txt of each host
+pub struct RobotsCache {
+    user_agent: String,
+    cache: Mutex<HashMap<String, (Instant, CachedRobotsTxt)>>,
+    //so the tasks waiting for the same robots.txt fetch it only once
+    fetching: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl RobotsCache {
+    pub fn new(user_agent: &str) -> Self {
+        Self {
+            user_agent: user_agent.to_string(),
+            cache: Mutex::new(HashMap::new()),
+            fetching: Mutex::new(HashMap::new()),
+        }
+    }
+
+    //fails if the robots.txt is unreachable, we dont crawl the host until we know what it allows.
+    //The robots.txt is fetched under a permit of the host, so the caller must not hold one.
+    pub async fn get(
+        &self,
+        http_client: &Client,
+        host_scheduler: &HostScheduler,
+        url: &Url,
+    ) -> Result<Arc<RobotsTxt>, CrawlError> {
+        let origin = url.origin().ascii_serialization();
+        if let Some(robots) = self.cached(&origin) {
+            return robots.map_err(CrawlError::RobotsTxt);
+        }
+
+        let lock = self
+            .fetching
+            .lock()
+            .unwrap()
+            .entry(origin.clone())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().await;
+        //another task may have fetched it while we waited
+        if let Some(robots) = self.cached(&origin) {
+            return robots.map_err(CrawlError::RobotsTxt);
+        }
+
+        //we dont know the crawl delay yet, the default one will do
+        let host_permit = host_scheduler
+            .acquire(url.host_str().unwrap_or_default(), None)
+            .await;
+        let robots = self.fetch(http_client, &origin).await.map(Arc::new);
+        drop(host_permit);
+        if let Err(e) = &robots {
+            log::debug!("Error fetching the robots.txt of {}: {}", origin, e);
+        }
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_ROBOTS_TXTS {
+            cache.retain(|_, (fetched_at, robots)| fetched_at.elapsed() < ttl(robots));
+        }
+        if cache.len() >= MAX_CACHED_ROBOTS_TXTS {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, (fetched_at, _))| *fetched_at)
+                .map(|(origin, _)| origin.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(origin.clone(), (Instant::now(), robots.clone()));
+        drop(cache);
+        self.fetching.lock().unwrap().remove(&origin);
+
+        robots.map_err(CrawlError::RobotsTxt)
+    }
+
+    fn cached(&self, origin: &str) -> Option<CachedRobotsTxt> {
+        match self.cache.lock().unwrap().get(origin) {
+            Some((fetched_at, robots)) if fetched_at.elapsed() < ttl(robots) => {
+                Some(robots.clone())
+            }
+            _ => None,
+        }
+    }
+
+    async fn fetch(&self, http_client: &Client, origin: &str) -> Result<RobotsTxt, String> {
+        let robots_url = format!("{origin}/robots.txt");
+        log::debug!("Fetching {}", robots_url);
+
+        let response = http_client
+            .get(&robots_url)
+            .send()
+            .await
+            .map_err(|e| CrawlError::from(e).to_string())?;
+        match response.status() {
+            StatusCode::OK => {
+                let body = fetch::read_body(response, MAX_ROBOTS_TXT_SIZE)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                //robots.txt files are utf-8 (RFC 9309)
+                Ok(RobotsTxt::parse(
+                    &String::from_utf8_lossy(&body),
+                    &self.user_agent,
+                ))
+            }
+            //a missing robots.txt means there are no restrictions
+            status if status.is_client_error() => Ok(RobotsTxt::default()),
+            //if the server is failing we dont know what is allowed
+            status => Err(format!("status {status}")),
+        }
+    }
+}
+
+fn ttl(robots: &CachedRobotsTxt) -> Duration {
+    match robots {
+        Ok(_) => ROBOTS_TXT_TTL,
+        Err(_) => ROBOTS_TXT_FAILURE_TTL,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn allowed(robots: &RobotsTxt, url: &str) -> bool {
+        robots.is_allowed(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn patterns_match_prefixes() {
+        assert!(pattern_matches("/", "/anything"));
+        assert!(pattern_matches("/private", "/private/page.html"));
+        assert!(pattern_matches("/private", "/private"));
+        assert!(!pattern_matches("/private", "/public"));
+        assert!(!pattern_matches("/private/", "/private"));
+    }
+
+    #[test]
+    fn patterns_match_wildcards() {
+        assert!(pattern_matches("/*.pdf", "/docs/file.pdf"));
+        assert!(pattern_matches("/*.pdf", "/docs/file.pdf?download=1"));
+        assert!(pattern_matches("/a*b*c", "/aXXbYYc"));
+        assert!(!pattern_matches("/a*b*c", "/aXXcYYb"));
+        assert!(pattern_matches("*", ""));
+        assert!(pattern_matches("/**", "/x"));
+    }
+
+    #[test]
+    fn dollar_anchors_the_end() {
+        assert!(pattern_matches("/*.pdf$", "/docs/file.pdf"));
+        assert!(!pattern_matches("/*.pdf$", "/docs/file.pdf?download=1"));
+        assert!(pattern_matches("/exact$", "/exact"));
+        assert!(!pattern_matches("/exact$", "/exact/more"));
+        assert!(pattern_matches("/$", "/"));
+        assert!(!pattern_matches("/$", "/page"));
+    }
+
+    #[test]
+    fn longest_rule_wins_and_allow_wins_ties() {
+        let robots = RobotsTxt::parse(
+            "User-agent: *\nDisallow: /shop\nAllow: /shop/public\nAllow: /tie\nDisallow: /tie",
+            "osse",
+        );
+        assert!(!allowed(&robots, "https://example.com/shop/cart"));
+        assert!(allowed(&robots, "https://example.com/shop/public/item"));
+        assert!(allowed(&robots, "https://example.com/tie"));
+        assert!(allowed(&robots, "https://example.com/other"));
+    }
+
+    #[test]
+    fn queries_are_matched_too() {
+        let robots = RobotsTxt::parse("User-agent: *\nDisallow: /*?sort=", "osse");
+        assert!(!allowed(&robots, "https://example.com/list?sort=asc"));
+        assert!(allowed(&robots, "https://example.com/list"));
+    }
+
+    #[test]
+    fn our_group_wins_over_the_wildcard_one() {
+        let content =
+            "User-agent: *\nDisallow: /\n\nUser-agent: OSSE\nDisallow: /private\nCrawl-delay: 2";
+        let robots = RobotsTxt::parse(content, "osse");
+        assert!(allowed(&robots, "https://example.com/page"));
+        assert!(!allowed(&robots, "https://example.com/private"));
+        assert_eq!(robots.crawl_delay, Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn user_agents_are_matched_exactly() {
+        //"se" and "o" are substrings of our user agent but not our product token
+        let content =
+            "User-agent: se\nUser-agent: o\nDisallow: /\n\nUser-agent: *\nDisallow: /wild";
+        let robots = RobotsTxt::parse(content, "OSSE");
+        assert!(allowed(&robots, "https://example.com/page"));
+        assert!(!allowed(&robots, "https://example.com/wild"));
+
+        let robots = RobotsTxt::parse("User-agent: osse\nDisallow: /", "OSSE");
+        assert!(!allowed(&robots, "https://example.com/page"));
+    }
+
+    #[test]
+    fn groups_can_have_several_user_agents() {
+        let content = "User-agent: otherbot\nUser-agent: osse\nDisallow: /both\n\nUser-agent: otherbot\nDisallow: /other";
+        let robots = RobotsTxt::parse(content, "osse");
+        assert!(!allowed(&robots, "https://example.com/both"));
+        assert!(allowed(&robots, "https://example.com/other"));
+    }
+
+    #[test]
+    fn other_groups_and_comments_are_ignored() {
+        let content = "# comment\nUser-agent: otherbot\nDisallow: / # everything\n\nSitemap: https://example.com/sitemap.xml\nUser-agent: *\nDisallow:";
+        let robots = RobotsTxt::parse(content, "osse");
+        assert!(allowed(&robots, "https://example.com/page"));
+        assert_eq!(robots.sitemaps, vec!["https://example.com/sitemap.xml"]);
+    }
+
+    #[test]
+    fn no_rules_allow_everything() {
+        let robots = RobotsTxt::parse("", "osse");
+        assert!(allowed(&robots, "https://example.com/anything"));
+        assert_eq!(robots.crawl_delay, None);
+    }
+
+    //a server answering every request with that status and body, returns its url and request counter
+    fn serve(status: &'static str, body: &'static str) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buffer = [0; 4096];
+                let _ = stream.read(&mut buffer);
+                counter.fetch_add(1, Ordering::SeqCst);
+                let response = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn robots_txt_is_fetched_once_per_origin() {
+        let (url, requests) = serve("200 OK", "User-agent: *\nDisallow: /private");
+        let cache = Arc::new(RobotsCache::new("osse"));
+        let client = Client::new();
+        let scheduler = Arc::new(HostScheduler::new(Duration::from_millis(0), 4));
+        let url = Url::parse(&url).unwrap();
+
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let (cache, client, scheduler, url) = (
+                    cache.clone(),
+                    client.clone(),
+                    scheduler.clone(),
+                    url.clone(),
+                );
+                tokio::spawn(async move { cache.get(&client, &scheduler, &url).await })
+            })
+            .collect();
+        for task in tasks {
+            let robots = task.await.unwrap().unwrap();
+            assert!(!robots.is_allowed(&url.join("/private").unwrap()));
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn failures_are_cached_and_missing_files_allow_everything() {
+        let (url, requests) = serve("503 Service Unavailable", "");
+        let cache = RobotsCache::new("osse");
+        let client = Client::new();
+        let scheduler = HostScheduler::new(Duration::from_millis(0), 4);
+        let url = Url::parse(&url).unwrap();
+
+        for _ in 0..2 {
+            let error = cache.get(&client, &scheduler, &url).await.unwrap_err();
+            assert!(matches!(error, CrawlError::RobotsTxt(_)));
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let (url, _) = serve("404 Not Found", "");
+        let url = Url::parse(&url).unwrap();
+        let robots = cache.get(&client, &scheduler, &url).await.unwrap();
+        assert!(robots.is_allowed(&url.join("/anything").unwrap()));
+    }
+}
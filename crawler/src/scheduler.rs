@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+//above this many tracked hosts we forget the idle ones
+const MAX_IDLE_HOSTS: usize = 10000;
+
+struct HostState {
+    connections: Arc<Semaphore>,
+    next_fetch: Instant,
+}
+
+//makes sure we dont hammer a host: limits the concurrent connections to it and spaces the requests
+pub struct HostScheduler {
+    min_delay: Duration,
+    max_connections_per_host: usize,
+    hosts: Mutex<HashMap<String, HostState>>,
+}
+
+impl HostScheduler {
+    pub fn new(min_delay: Duration, max_connections_per_host: usize) -> Self {
+        Self {
+            min_delay,
+            max_connections_per_host,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    //waits until we are allowed to fetch from the host. The returned permit must be held during the request.
+    pub async fn acquire(&self, host: &str, crawl_delay: Option<Duration>) -> OwnedSemaphorePermit {
+        let connections = self.host_state(host, |state| state.connections.clone());
+        let permit = connections.acquire_owned().await;
+
+        //we reserve the next free slot of the host and wait for it
+        let delay = crawl_delay.map_or(self.min_delay, |d| d.max(self.min_delay));
+        let fetch_at = self.host_state(host, |state| {
+            let fetch_at = state.next_fetch.max(Instant::now());
+            state.next_fetch = fetch_at + delay;
+            fetch_at
+        });
+        tokio::time::delay_until(fetch_at).await;
+
+        permit
+    }
+
+    //makes us wait before fetching from the host again, e.g. when it tells us we are going too fast
+    pub fn back_off(&self, host: &str, delay: Duration) {
+        self.host_state(host, |state| {
+            state.next_fetch = state.next_fetch.max(Instant::now() + delay);
+        });
+    }
+
+    fn host_state<T>(&self, host: &str, f: impl FnOnce(&mut HostState) -> T) -> T {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        if hosts.len() > MAX_IDLE_HOSTS {
+            let now = Instant::now();
+            let max_connections = self.max_connections_per_host;
+            hosts.retain(|_, state| {
+                state.next_fetch > now || state.connections.available_permits() < max_connections
+            });
+        }
+
+        let state = hosts.entry(host.to_string()).or_insert_with(|| HostState {
+            connections: Arc::new(Semaphore::new(self.max_connections_per_host)),
+            next_fetch: Instant::now(),
+        });
+        f(state)
+    }
+}
@@ -0,0 +1,70 @@
+use crate::config::CrawlerConfig;
+use regex::Regex;
+use url::Url;
+
+//decides which urls the crawler is allowed to visit. if no domains or patterns are allowed
+//every url is in scope, otherwise we are in focused mode and only crawl the matching ones.
+pub struct CrawlScope {
+    allowed_domains: Vec<String>,
+    blocked_domains: Vec<String>,
+    allowed_url_patterns: Vec<Regex>,
+    include_subdomains: bool,
+}
+
+impl CrawlScope {
+    pub fn new(config: &CrawlerConfig) -> Result<Self, String> {
+        let allowed_url_patterns = config
+            .allowed_url_patterns
+            .iter()
+            .map(|p| Regex::new(p).map_err(|e| format!("Invalid url pattern {p}: {e}")))
+            .collect::<Result<Vec<Regex>, String>>()?;
+
+        let normalise = |domains: &Vec<String>| {
+            domains
+                .iter()
+                .map(|d| d.trim().trim_end_matches('.').to_lowercase())
+                .collect()
+        };
+
+        Ok(Self {
+            allowed_domains: normalise(&config.allowed_domains),
+            blocked_domains: normalise(&config.blocked_domains),
+            allowed_url_patterns,
+            include_subdomains: config.include_subdomains,
+        })
+    }
+
+    pub fn is_focused(&self) -> bool {
+        !self.allowed_domains.is_empty() || !self.allowed_url_patterns.is_empty()
+    }
+
+    pub fn contains(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            None => return false,
+            Some(host) => host.to_lowercase(),
+        };
+
+        //blocked domains always include their subdomains
+        if self
+            .blocked_domains
+            .iter()
+            .any(|domain| host == *domain || host.ends_with(&format!(".{domain}")))
+        {
+            return false;
+        }
+
+        if !self.is_focused() {
+            return true;
+        }
+
+        let domain_allowed = self.allowed_domains.iter().any(|domain| {
+            host == *domain || (self.include_subdomains && host.ends_with(&format!(".{domain}")))
+        });
+
+        domain_allowed
+            || self
+                .allowed_url_patterns
+                .iter()
+                .any(|pattern| pattern.is_match(url.as_str()))
+    }
+}
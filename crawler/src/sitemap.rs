@@ -0,0 +1,220 @@
+use crate::fetch;
+use flate2::read::GzDecoder;
+use reqwest::{Client, StatusCode};
+use std::io::Read;
+
+//the sitemaps protocol doesnt allow more urls per file
+const MAX_URLS_PER_SITEMAP: usize = 50000;
+
+#[derive(Debug, Clone)]
+pub struct SitemapUrl {
+    pub loc: String,
+    pub priority: Option<f64>,
+    pub modified_at: Option<u64>, //unix seconds
+}
+
+#[derive(Debug)]
+pub enum Sitemap {
+    //an index just lists other sitemaps
+    Index(Vec<String>),
+    UrlSet(Vec<SitemapUrl>),
+}
+
+pub async fn fetch(http_client: &Client, url: &str, max_size: usize) -> Result<Sitemap, String> {
+    log::debug!("Fetching sitemap {}", url);
+
+    let response = match http_client.get(url).send().await {
+        Ok(response) if response.status() == StatusCode::OK => response,
+        Ok(response) => return Err(format!("Error fetching {url}: {}", response.status())),
+        Err(e) => return Err(format!("Error fetching {url}: {e}")),
+    };
+    let mut body = fetch::read_body(response, max_size)
+        .await
+        .map_err(|e| format!("Error fetching {url}: {e}"))?;
+
+    //sitemap.xml.gz files are usually served as they are, not with a gzip content encoding
+    if body.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = vec![];
+        GzDecoder::new(body.as_slice())
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut decompressed)
+            .map_err(|e| format!("Error decompressing {url}: {e}"))?;
+        if decompressed.len() > max_size {
+            return Err(format!(
+                "Sitemap too large ({url}), max size is {max_size} bytes"
+            ));
+        }
+        body = decompressed;
+    }
+
+    //sitemaps must be utf-8
+    parse(&String::from_utf8_lossy(&body)).map_err(|e| format!("Error parsing {url}: {e}"))
+}
+
+pub fn parse(content: &str) -> Result<Sitemap, String> {
+    let content = content.trim_start_matches('\u{feff}').trim();
+
+    //plain text sitemaps are just one url per line
+    if !content.starts_with('<') {
+        return Ok(Sitemap::UrlSet(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .take(MAX_URLS_PER_SITEMAP)
+                .map(|loc| SitemapUrl {
+                    loc: loc.to_string(),
+                    priority: None,
+                    modified_at: None,
+                })
+                .collect(),
+        ));
+    }
+
+    let document = roxmltree::Document::parse(content).map_err(|e| e.to_string())?;
+    let root = document.root_element();
+
+    //text of the child element with that name, e.g. <loc>
+    let child_text = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|c| c.is_element() && c.tag_name().name() == name)
+            .and_then(|c| c.text())
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+    };
+    let entries = |name: &'static str| {
+        root.children()
+            .filter(move |c| c.is_element() && c.tag_name().name() == name)
+            .take(MAX_URLS_PER_SITEMAP)
+    };
+
+    match root.tag_name().name() {
+        "sitemapindex" => Ok(Sitemap::Index(
+            entries("sitemap")
+                .filter_map(|sitemap| child_text(sitemap, "loc"))
+                .collect(),
+        )),
+        "urlset" => Ok(Sitemap::UrlSet(
+            entries("url")
+                .filter_map(|url| {
+                    Some(SitemapUrl {
+                        loc: child_text(url, "loc")?,
+                        priority: child_text(url, "priority")
+                            .and_then(|p| p.parse::<f64>().ok())
+                            .filter(|p| (0.0..=1.0).contains(p)),
+                        modified_at: child_text(url, "lastmod").and_then(|l| parse_lastmod(&l)),
+                    })
+                })
+                .collect(),
+        )),
+        other => Err(format!("Unknown sitemap root element <{other}>")),
+    }
+}
+
+//lastmod is a W3C datetime (e.g. 2022-11-05 or 2022-11-05T10:00:00+00:00), we only care about the day
+fn parse_lastmod(lastmod: &str) -> Option<u64> {
+    let date = lastmod.split('T').next()?;
+    let mut fields = date.split('-');
+    let year: i64 = fields.next()?.parse().ok()?;
+    let month: i64 = fields.next().map_or(Some(1), |m| m.parse().ok())?;
+    let day: i64 = fields.next().map_or(Some(1), |d| d.parse().ok())?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    //days since the unix epoch of a gregorian date (Howard Hinnant's days_from_civil)
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    u64::try_from(days * 60 * 60 * 24).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_url_sets() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <url>
+                    <loc> https://example.com/ </loc>
+                    <lastmod>2022-11-05</lastmod>
+                    <priority>0.8</priority>
+                </url>
+                <url><loc>https://example.com/other</loc><priority>7</priority></url>
+                <url><priority>0.5</priority></url>
+            </urlset>"#;
+        let urls = match parse(content).unwrap() {
+            Sitemap::UrlSet(urls) => urls,
+            sitemap => panic!("expected a url set, got {sitemap:?}"),
+        };
+
+        assert_eq!(urls.len(), 2);
+        assert_eq!(urls[0].loc, "https://example.com/");
+        assert_eq!(urls[0].priority, Some(0.8));
+        assert_eq!(urls[0].modified_at, Some(1667606400));
+        assert_eq!(urls[1].loc, "https://example.com/other");
+        //out of range
+        assert_eq!(urls[1].priority, None);
+        assert_eq!(urls[1].modified_at, None);
+    }
+
+    #[test]
+    fn parses_indexes() {
+        let content = "\u{feff}<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">
+            <sitemap><loc>https://example.com/a.xml</loc></sitemap>
+            <sitemap><loc>https://example.com/b.xml.gz</loc><lastmod>2022-01-01</lastmod></sitemap>
+        </sitemapindex>";
+        match parse(content).unwrap() {
+            Sitemap::Index(sitemaps) => assert_eq!(
+                sitemaps,
+                vec!["https://example.com/a.xml", "https://example.com/b.xml.gz"]
+            ),
+            sitemap => panic!("expected an index, got {sitemap:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_plain_text_sitemaps() {
+        let urls = match parse("https://example.com/a\n\n  https://example.com/b  \n").unwrap() {
+            Sitemap::UrlSet(urls) => urls,
+            sitemap => panic!("expected a url set, got {sitemap:?}"),
+        };
+        let locs: Vec<&str> = urls.iter().map(|u| u.loc.as_str()).collect();
+        assert_eq!(locs, vec!["https://example.com/a", "https://example.com/b"]);
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        assert!(parse("<html><body></body></html>").is_err());
+        assert!(parse("<urlset><url>").is_err());
+    }
+
+    #[test]
+    fn parses_lastmod_dates() {
+        assert_eq!(parse_lastmod("1970-01-01"), Some(0));
+        assert_eq!(parse_lastmod("2022-11-05"), Some(1667606400));
+        assert_eq!(parse_lastmod("2022-11-05T10:00:00+00:00"), Some(1667606400));
+        assert_eq!(parse_lastmod("2022-11"), Some(1667260800));
+        assert_eq!(parse_lastmod("2022"), Some(1640995200));
+        assert_eq!(parse_lastmod("2000-02-29"), Some(951782400));
+        assert_eq!(parse_lastmod("2100-03-01"), Some(4107542400));
+    }
+
+    #[test]
+    fn rejects_invalid_lastmod_dates() {
+        assert_eq!(parse_lastmod(""), None);
+        assert_eq!(parse_lastmod("yesterday"), None);
+        assert_eq!(parse_lastmod("2022-13-01"), None);
+        assert_eq!(parse_lastmod("2022-00-10"), None);
+        assert_eq!(parse_lastmod("2022-11-32"), None);
+        //before the unix epoch
+        assert_eq!(parse_lastmod("1969-12-31"), None);
+    }
+}
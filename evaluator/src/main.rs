@@ -0,0 +1,280 @@
+use indexer::indexer_implementation::IndexerImplementation;
+use indexer::ranking::{RankerChain, DEFAULT_RANKERS};
+use indexer::Indexer;
+use itertools::Itertools;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+//number of results taken into account by the metrics
+const CUTOFF: usize = 10;
+
+const USAGE: &str =
+    "Usage: evaluator <judged queries json> <index json> [--rankers <spec>] [--compare <spec>]";
+
+//a query along with the graded relevance of the urls we expect for it (0 = not relevant)
+#[derive(Debug, Deserialize)]
+struct JudgedQuery {
+    query: String,
+    judgements: HashMap<String, u32>,
+}
+
+struct Args {
+    queries_path: String,
+    index_path: String,
+    rankers: String,
+    compare_rankers: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Metrics {
+    ndcg: f64,
+    mrr: f64,
+    precision: f64,
+    recall: f64,
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    if let Err(e) = run() {
+        log::error!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let args = parse_args(std::env::args().skip(1).collect())?;
+
+    let queries_file = std::fs::read_to_string(&args.queries_path)
+        .map_err(|e| format!("Error reading {}: {e}", args.queries_path))?;
+    let queries: Vec<JudgedQuery> = serde_json::from_str(&queries_file)
+        .map_err(|e| format!("Error parsing {}: {e}", args.queries_path))?;
+
+    log::info!("Evaluating {} queries", queries.len());
+
+    let metrics = evaluate(&args.index_path, &args.rankers, &queries)?;
+
+    match &args.compare_rankers {
+        None => print_report(&queries, &metrics),
+        Some(compare_rankers) => {
+            let compare_metrics = evaluate(&args.index_path, compare_rankers, &queries)?;
+            print_comparison(&queries, &metrics, &compare_metrics);
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_args(args: Vec<String>) -> Result<Args, String> {
+    let mut positional = vec![];
+    let mut rankers = DEFAULT_RANKERS.to_string();
+    let mut compare_rankers = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rankers" => rankers = args.next().ok_or(USAGE)?,
+            "--compare" => compare_rankers = Some(args.next().ok_or(USAGE)?),
+            _ => positional.push(arg),
+        }
+    }
+
+    match positional.as_slice() {
+        [queries_path, index_path] => Ok(Args {
+            queries_path: queries_path.clone(),
+            index_path: index_path.clone(),
+            rankers,
+            compare_rankers,
+        }),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn evaluate(
+    index_path: &str,
+    rankers: &str,
+    queries: &[JudgedQuery],
+) -> Result<Vec<Metrics>, String> {
+    let rankers = RankerChain::from_spec(rankers)?;
+    log::info!("Ranking with: {}", rankers.describe());
+
+    let indexer = IndexerImplementation::load(index_path, rankers)?;
+
+    queries
+        .iter()
+        .map(|q| {
+            let results = ranked_urls(&indexer, &q.query)?;
+            Ok(calculate_metrics(&results, &q.judgements))
+        })
+        .collect()
+}
+
+//urls of the results, best first (ties are broken by url so runs are comparable)
+fn ranked_urls(indexer: &dyn Indexer, query: &str) -> Result<Vec<String>, String> {
+    Ok(indexer
+        .search(query)?
+        .into_iter()
+        .sorted_by(|a, b| a.cmp(b).then_with(|| a.url.cmp(&b.url)))
+        .map(|r| r.url)
+        .collect())
+}
+
+fn calculate_metrics(results: &[String], judgements: &HashMap<String, u32>) -> Metrics {
+    let grade = |url: &String| judgements.get(url).copied().unwrap_or(0);
+    let gain = |grade: u32| 2f64.powi(grade as i32) - 1.0;
+    let discount = |position: usize| (position as f64 + 2.0).log2();
+
+    let top_results = &results[..results.len().min(CUTOFF)];
+
+    let dcg: f64 = top_results
+        .iter()
+        .enumerate()
+        .fold(0.0, |dcg, (i, url)| dcg + gain(grade(url)) / discount(i));
+    let ideal_dcg: f64 = judgements
+        .values()
+        .sorted()
+        .rev()
+        .take(CUTOFF)
+        .enumerate()
+        .fold(0.0, |dcg, (i, grade)| dcg + gain(*grade) / discount(i));
+
+    let relevant_results = top_results.iter().filter(|url| grade(url) > 0).count();
+    let relevant_total = judgements.values().filter(|grade| **grade > 0).count();
+
+    let first_relevant = top_results.iter().position(|url| grade(url) > 0);
+
+    Metrics {
+        ndcg: if ideal_dcg > 0.0 {
+            dcg / ideal_dcg
+        } else {
+            0.0
+        },
+        mrr: first_relevant.map_or(0.0, |position| 1.0 / (position as f64 + 1.0)),
+        //missing results count as irrelevant ones, like trec_eval does
+        precision: relevant_results as f64 / CUTOFF as f64,
+        recall: if relevant_total == 0 {
+            0.0
+        } else {
+            relevant_results as f64 / relevant_total as f64
+        },
+    }
+}
+
+fn mean(metrics: &[Metrics]) -> Metrics {
+    if metrics.is_empty() {
+        return Metrics::default();
+    }
+
+    let n = metrics.len() as f64;
+    Metrics {
+        ndcg: metrics.iter().map(|m| m.ndcg).sum::<f64>() / n,
+        mrr: metrics.iter().map(|m| m.mrr).sum::<f64>() / n,
+        precision: metrics.iter().map(|m| m.precision).sum::<f64>() / n,
+        recall: metrics.iter().map(|m| m.recall).sum::<f64>() / n,
+    }
+}
+
+fn print_report(queries: &[JudgedQuery], metrics: &[Metrics]) {
+    println!(
+        "{:<40} {:>8} {:>8} {:>8} {:>8}",
+        "query", "nDCG@10", "MRR@10", "P@10", "R@10"
+    );
+    let print_row = |name: &str, m: &Metrics| {
+        println!(
+            "{:<40} {:>8.4} {:>8.4} {:>8.4} {:>8.4}",
+            name, m.ndcg, m.mrr, m.precision, m.recall
+        )
+    };
+
+    for (query, m) in queries.iter().zip(metrics) {
+        print_row(&query.query, m);
+    }
+    print_row("MEAN", &mean(metrics));
+}
+
+fn print_comparison(queries: &[JudgedQuery], baseline: &[Metrics], candidate: &[Metrics]) {
+    println!(
+        "{:<40} {:>10} {:>10} {:>10}",
+        "query", "nDCG@10 A", "nDCG@10 B", "delta"
+    );
+    for ((query, a), b) in queries.iter().zip(baseline).zip(candidate) {
+        println!(
+            "{:<40} {:>10.4} {:>10.4} {:>+10.4}",
+            query.query,
+            a.ndcg,
+            b.ndcg,
+            b.ndcg - a.ndcg
+        );
+    }
+
+    let (a, b) = (mean(baseline), mean(candidate));
+    println!();
+    println!("{:<40} {:>10} {:>10} {:>10}", "mean", "A", "B", "delta");
+    for (name, a, b) in [
+        ("nDCG@10", a.ndcg, b.ndcg),
+        ("MRR@10", a.mrr, b.mrr),
+        ("P@10", a.precision, b.precision),
+        ("R@10", a.recall, b.recall),
+    ] {
+        println!("{:<40} {:>10.4} {:>10.4} {:>+10.4}", name, a, b, b - a);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(urls: &[&str]) -> Vec<String> {
+        urls.iter().map(|u| u.to_string()).collect()
+    }
+
+    fn judgements(grades: &[(&str, u32)]) -> HashMap<String, u32> {
+        grades.iter().map(|(u, g)| (u.to_string(), *g)).collect()
+    }
+
+    #[test]
+    fn perfect_ranking() {
+        let judgements = judgements(&[("a", 2), ("b", 1), ("c", 0)]);
+        let metrics = calculate_metrics(&urls(&["a", "b"]), &judgements);
+        assert_eq!(metrics.ndcg, 1.0);
+        assert_eq!(metrics.mrr, 1.0);
+        assert_eq!(metrics.precision, 0.2);
+        assert_eq!(metrics.recall, 1.0);
+    }
+
+    #[test]
+    fn worse_rankings_score_lower() {
+        let judgements = judgements(&[("a", 2), ("b", 1)]);
+        let metrics = calculate_metrics(&urls(&["x", "b", "a"]), &judgements);
+        //dcg: 1/log2(3) + 3/log2(4), ideal: 3/log2(2) + 1/log2(3)
+        let expected = (1.0 / 3f64.log2() + 1.5) / (3.0 + 1.0 / 3f64.log2());
+        assert!((metrics.ndcg - expected).abs() < 1e-9);
+        assert_eq!(metrics.mrr, 0.5);
+        assert_eq!(metrics.precision, 0.2);
+        assert_eq!(metrics.recall, 1.0);
+    }
+
+    #[test]
+    fn only_the_top_results_count() {
+        let judgements = judgements(&[("relevant", 1)]);
+        let mut results: Vec<String> = (0..CUTOFF).map(|i| format!("other{i}")).collect();
+        results.push("relevant".to_string());
+
+        let metrics = calculate_metrics(&results, &judgements);
+        assert_eq!(metrics.ndcg, 0.0);
+        assert_eq!(metrics.mrr, 0.0);
+        assert_eq!(metrics.precision, 0.0);
+        assert_eq!(metrics.recall, 0.0);
+    }
+
+    #[test]
+    fn no_results_or_judgements() {
+        let metrics = calculate_metrics(&[], &judgements(&[("a", 1)]));
+        assert_eq!(metrics.precision, 0.0);
+        assert_eq!(metrics.recall, 0.0);
+
+        let metrics = calculate_metrics(&urls(&["a"]), &HashMap::new());
+        assert_eq!(metrics.ndcg, 0.0);
+        assert_eq!(metrics.recall, 0.0);
+    }
+}
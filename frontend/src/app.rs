@@ -11,6 +11,83 @@ use yew_router::scope_ext::RouterScopeExt;
 #[derive(Properties, Clone, PartialEq, Eq)]
 pub struct ResultComponentProps {
     result: IndexedResource,
+    api_endpoint: String,
+    query: String,
+    debug: bool,
+}
+
+#[derive(Properties, Clone, PartialEq, Eq)]
+pub struct ExplanationComponentProps {
+    api_endpoint: String,
+    query: String,
+    url: String,
+}
+
+//fetches and shows how the score of a result was computed
+#[function_component(ExplanationComponent)]
+fn explanation_component(props: &ExplanationComponentProps) -> Html {
+    let explanation = use_state(|| None as Option<Result<ScoreExplanation, String>>);
+
+    {
+        let explanation = explanation.clone();
+        use_effect_with_deps(
+            move |(api_endpoint, query, url)| {
+                let endpoint = format!(
+                    "{}/explain?q={}&url={}",
+                    api_endpoint,
+                    urlencoding::encode(query),
+                    urlencoding::encode(url)
+                );
+                wasm_bindgen_futures::spawn_local(async move {
+                    let fetched_explanation = match Request::get(endpoint.as_str()).send().await {
+                        Err(_) => Err("Failed to connect to the API!".to_string()),
+                        Ok(response) => match response.json::<ScoreExplanation>().await {
+                            Err(_) => Err("Internal API Error!".to_string()),
+                            Ok(json) => Ok(json),
+                        },
+                    };
+                    explanation.set(Some(fetched_explanation));
+                });
+                || ()
+            },
+            (
+                props.api_endpoint.clone(),
+                props.query.clone(),
+                props.url.clone(),
+            ),
+        );
+    }
+
+    let explanation = match &*explanation {
+        None => return html! { <p class="text-muted small">{"Loading explanation..."}</p> },
+        Some(Err(error)) => {
+            return html! { <p class="text-muted small">{format!("ERROR: {}", error)}</p> }
+        }
+        Some(Ok(explanation)) => explanation,
+    };
+
+    html! {
+        <div class="small text-muted border-start ps-2 mb-2">
+            <p class="mb-1">{format!("Score: {:.2} (matches query: {})", explanation.score, explanation.matches_query)}</p>
+            <ul class="mb-1">
+                {explanation.rankers.iter().map(|r| html! {
+                    <li>{format!("{}: {:.2} x {} = {:.2}", r.name, r.score, r.weight, r.score * r.weight)}</li>
+                }).collect::<Html>()}
+            </ul>
+            <ul class="mb-1">
+                {explanation.terms.iter().map(|t| html! {
+                    <li>{format!(
+                        "\"{}\": +{:.2} (occurences: {}, main content occurences: {}, anchor occurences: {}, title: {}, description: {}, url: {}, headings: {}, keywords: {}, image alts: {})",
+                        t.term, t.contribution, t.occurences, t.main_occurences, t.anchor_occurences, t.in_title, t.in_description, t.in_url,
+                        t.in_headings, t.in_keywords, t.in_image_alts
+                    )}</li>
+                }).collect::<Html>()}
+            </ul>
+            <p class="mb-0">
+                {explanation.signals.iter().map(|s| format!("{}: {:.2}", s.name, s.value)).join(", ")}
+            </p>
+        </div>
+    }
 }
 
 #[function_component(ResultComponent)]
@@ -51,17 +128,40 @@ fn result_component(props: &ResultComponentProps) -> Html {
         }
     }
 
+    //author and publication date from the page's structured data (we only show the day of iso 8601 dates)
+    //and when it was crawled
+    let date = props
+        .result
+        .date_published
+        .as_ref()
+        .and_then(|d| d.split('T').next().map(String::from));
+    let crawled = props
+        .result
+        .crawled_at
+        .map(|t| format!("crawled {}", format_date(t)));
+    let byline = [props.result.author.clone(), date, crawled]
+        .into_iter()
+        .flatten()
+        .join(" · ");
+
     html! {
         <div class={format!("mb-4 {}", style)}>
             <a href={props.result.url.clone()}>
                 <p class="url text-muted mb-0">{props.result.url.clone()}</p>
-                <p class="title mb-1">{match props.result.title.clone() {
+                <p class="title mb-1">
+                if props.result.filetype != FILETYPE_HTML {
+                    <span class="badge bg-secondary me-2">{props.result.filetype.to_uppercase()}</span>
+                }
+                {match props.result.title.clone() {
                     None => "No Title".to_string(),
                     Some(title) => {
                         truncate(&title, 70)
                 },
                 }}</p>
             </a>
+            if !byline.is_empty() {
+                <p class="url text-muted mb-1">{byline}</p>
+            }
             <p class="description">
                 {match props.result.description.clone() {
                     None => "No Description.".to_string(),
@@ -70,6 +170,9 @@ fn result_component(props: &ResultComponentProps) -> Html {
                 },
                 }}{format!("PRIO: {}", props.result.priority)}
             </p>
+            if props.debug {
+                <ExplanationComponent api_endpoint={props.api_endpoint.clone()} query={props.query.clone()} url={props.result.url.clone()} />
+            }
         </div>
     }
 }
@@ -82,6 +185,7 @@ pub struct SearchResult {
 pub struct OSSE {
     pub current_search_query: String,
     pub results: Option<SearchResult>, //none signifies no query yet
+    pub debug: bool,                   //show how each result was ranked
 }
 
 #[derive(Properties, PartialEq, Eq)]
@@ -94,6 +198,7 @@ pub enum OSSEMessage {
     SearchSubmitted,
     SearchChanged(String),
     SearchFinished(Result<Vec<IndexedResource>, String>),
+    DebugToggled,
 }
 
 impl Component for OSSE {
@@ -116,6 +221,7 @@ impl Component for OSSE {
                 .unwrap()
                 .to_string(),
             results: None,
+            debug: false,
         }
     }
 
@@ -184,6 +290,11 @@ impl Component for OSSE {
                     }
                 };
 
+                true
+            }
+            OSSEMessage::DebugToggled => {
+                self.debug = !self.debug;
+
                 true
             }
         }
@@ -205,6 +316,8 @@ impl Component for OSSE {
             OSSEMessage::SearchChanged(input)
         });
 
+        let ondebugtoggle = ctx.link().callback(|_: Event| OSSEMessage::DebugToggled);
+
         let display_results = |maybe_results: &Option<SearchResult>| -> Html {
             //not yet searched
             if maybe_results.is_none() {
@@ -253,7 +366,7 @@ impl Component for OSSE {
                     .map(|r| {
                         html! {
                             <div key={r.url.to_owned()}>
-                                <ResultComponent result={r.clone()} />
+                                <ResultComponent result={r.clone()} api_endpoint={ctx.props().api_endpoint.clone()} query={search_query.clone()} debug={self.debug} />
                             </div>
                         }
                     })
@@ -291,6 +404,10 @@ impl Component for OSSE {
                                             <button class="btn btn-primary" type="submit" >{"Search!"}</button>
                                         </div>
                                     </form>
+                                    <div class="form-check form-switch mb-2">
+                                        <input class="form-check-input" type="checkbox" id="debug-toggle" checked={self.debug} onchange={ondebugtoggle} />
+                                        <label class="form-check-label text-muted" for="debug-toggle">{"Explain ranking"}</label>
+                                    </div>
                                 </section>
                                 <section>
                                     {display_results(&self.results)}
@@ -1,104 +1,877 @@
+use crate::ranking::{DocumentFeatures, RankerChain, TermFeatures};
 use lib::lib::*;
 use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
 
+//what we store about each indexed page
+#[derive(Serialize, Deserialize)]
+pub struct IndexedDocument {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    #[serde(default = "default_filetype")]
+    pub filetype: String,
+    pub url_priority: u32,
+    pub indexed_at: u64,
+    #[serde(default)]
+    pub headings: Vec<String>,
+    #[serde(default)]
+    pub og_title: Option<String>,
+    #[serde(default)]
+    pub og_description: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub image_alts: Vec<String>,
+    #[serde(default)]
+    pub structured_data: StructuredData,
+    //how and when the crawler fetched it
+    #[serde(default)]
+    pub crawl: Option<CrawlMetadata>,
+    //the stemmed words it has in database and main_database, so it can be forgotten without looking
+    //through them all (none for indexes saved before we kept them)
+    #[serde(default)]
+    pub words: Option<Vec<String>>,
+}
+
+impl IndexedDocument {
+    //the title we show, falling back to the open graph and structured data ones
+    fn display_title(&self) -> Option<&String> {
+        self.title
+            .as_ref()
+            .or(self.og_title.as_ref())
+            .or(self.structured_data.name.as_ref())
+    }
+
+    fn display_description(&self) -> Option<&String> {
+        self.description
+            .as_ref()
+            .or(self.og_description.as_ref())
+            .or(self.structured_data.description.as_ref())
+    }
+
+    //when the crawler fetched it, or when we got it if we dont know
+    fn crawled_at(&self) -> u64 {
+        self.crawl
+            .as_ref()
+            .map_or(self.indexed_at, |c| c.fetched_at)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct IndexerImplementation {
-    pub database: HashMap<String, HashSet<IndexedResource>>,
+    //stemmed word -> (url -> occurences in the page)
+    pub database: HashMap<String, HashMap<String, u32>>,
+    //anchor text is kept as a separate field: stemmed word -> (target url -> occurences)
+    pub anchor_database: HashMap<String, HashMap<String, u32>>,
+    //and so is the main content of the pages: stemmed word -> (url -> occurences in the main content)
+    #[serde(default)]
+    pub main_database: HashMap<String, HashMap<String, u32>>,
+    pub documents: HashMap<String, IndexedDocument>,
+    //url -> number of pages linking to it
+    pub inlinks: HashMap<String, u32>,
+    //what each page added to anchor_database and inlinks: source url -> (target url -> (stemmed word ->
+    //occurences)), so it can be replaced when the page is indexed again
+    #[serde(default)]
+    pub anchors: HashMap<String, HashMap<String, HashMap<String, u32>>>,
+    //pages that asked not to be indexed, they arent shown even if other pages link to them
+    #[serde(default)]
+    pub noindex: HashSet<String>,
+    //urls that redirect to another one: alias -> url
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    #[serde(skip)]
+    rankers: RankerChain,
+    #[serde(skip, default = "create_stemmer")]
     stemmer: Stemmer,
 }
 
+fn create_stemmer() -> Stemmer {
+    Stemmer::create(Algorithm::English) //todo: depend on lang
+}
+
 impl IndexerImplementation {
-    pub fn new() -> Self {
+    pub fn new(rankers: RankerChain) -> Self {
         Self {
             database: HashMap::new(),
-            stemmer: Stemmer::create(Algorithm::English), //todo: depend on lang
+            anchor_database: HashMap::new(),
+            main_database: HashMap::new(),
+            documents: HashMap::new(),
+            inlinks: HashMap::new(),
+            anchors: HashMap::new(),
+            noindex: HashSet::new(),
+            aliases: HashMap::new(),
+            rankers,
+            stemmer: create_stemmer(),
+        }
+    }
+
+    //loads an index previously written by save()
+    pub fn load(path: &str, rankers: RankerChain) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Error opening index {path}: {e}"))?;
+        let mut indexer: Self = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format!("Error reading index {path}: {e}"))?;
+        indexer.rankers = rankers;
+        Ok(indexer)
+    }
+
+    //urls of the pages containing the word or whose incoming links contain it
+    fn search_word_in_db(&self, stemmed_word: &str) -> HashSet<&String> {
+        let content_urls = self
+            .database
+            .get(stemmed_word)
+            .into_iter()
+            .flat_map(|r| r.keys());
+        let anchor_urls = self
+            .anchor_database
+            .get(stemmed_word)
+            .into_iter()
+            .flat_map(|r| r.keys());
+        content_urls.chain(anchor_urls).collect()
+    }
+
+    //the url the given one redirects to, if any
+    fn resolve<'a>(&'a self, url: &'a str) -> &'a str {
+        let mut resolved = url;
+        //the aliases shouldnt form loops, but we dont want to hang if they do
+        for _ in 0..=self.aliases.len() {
+            match self.aliases.get(resolved) {
+                Some(target) if target != url => resolved = target,
+                _ => break,
+            }
         }
+        resolved
     }
 
-    fn search_word_in_db(&self, word: String) -> Option<&HashSet<IndexedResource>> {
-        self.database.get(&word)
+    //drops the words and document of the url
+    fn forget(&mut self, url: &str) {
+        //words are only ever stored along with the document
+        let words = match self.documents.remove(url) {
+            None => return,
+            Some(document) => document.words,
+        };
+        for database in [&mut self.database, &mut self.main_database] {
+            match &words {
+                None => database.retain(|_, urls| {
+                    urls.remove(url);
+                    !urls.is_empty()
+                }),
+                Some(words) => {
+                    for word in words {
+                        if let Some(urls) = database.get_mut(word) {
+                            urls.remove(url);
+                            if urls.is_empty() {
+                                database.remove(word);
+                            }
+                        }
+                    }
+                }
+            }
+        }
     }
 
-    fn calculate_word_priority(word: &str, _html_site: &str, words: &[String]) -> u32 {
-        //TODO: priorize lower levels of url, priorize word in url/title/description or main?
+    //takes out the anchor text and inlinks the links of the page added
+    fn forget_anchors(&mut self, source_url: &str) {
+        for (target_url, words) in self.anchors.remove(source_url).unwrap_or_default() {
+            for (stemmed_word, count) in words {
+                if let Some(urls) = self.anchor_database.get_mut(&stemmed_word) {
+                    decrement(urls, &target_url, count);
+                    if urls.is_empty() {
+                        self.anchor_database.remove(&stemmed_word);
+                    }
+                }
+            }
+            decrement(&mut self.inlinks, &target_url, 1);
+        }
+    }
 
-        //TODO: levshtein
+    fn stemmed_words(&self, text: &str) -> HashSet<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| self.stemmer.stem(&w.to_lowercase()).to_string())
+            .collect()
+    }
 
-        //atm priority is just the number of occurences in the site.
-        words.iter().filter(|w| *w == word).count() as u32
+    //returns the lowercased query words and their stems
+    fn normalise_query(&self, term: &str) -> (Vec<String>, Vec<String>) {
+        let query: Vec<String> = term
+            .split(' ')
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_ascii_lowercase())
+            .collect();
+        let stemmed_query = query
+            .iter()
+            .map(|w| self.stemmer.stem(w).to_string())
+            .collect();
+        (query, stemmed_query)
     }
+
+    //TODO: levshtein
+    fn calculate_features(&self, url: &str, stemmed_query: &[String]) -> DocumentFeatures {
+        let document = self.documents.get(url);
+        //the words of all the given fields of the document
+        let field_words = |fields: &dyn Fn(&IndexedDocument) -> Vec<&String>| {
+            document.map_or_else(HashSet::new, |d| {
+                fields(d)
+                    .into_iter()
+                    .flat_map(|text| self.stemmed_words(text))
+                    .collect()
+            })
+        };
+        let title_words = field_words(&|d| {
+            [&d.title, &d.og_title, &d.structured_data.name]
+                .into_iter()
+                .flatten()
+                .collect()
+        });
+        let description_words = field_words(&|d| {
+            [
+                &d.description,
+                &d.og_description,
+                &d.structured_data.description,
+            ]
+            .into_iter()
+            .flatten()
+            .collect()
+        });
+        let heading_words = field_words(&|d| d.headings.iter().collect());
+        let keyword_words = field_words(&|d| d.keywords.iter().collect());
+        let image_alt_words = field_words(&|d| d.image_alts.iter().collect());
+        let url_words = self.stemmed_words(url);
+
+        let occurences_in = |database: &HashMap<String, HashMap<String, u32>>, term: &String| {
+            database
+                .get(term)
+                .and_then(|r| r.get(url))
+                .copied()
+                .unwrap_or(0)
+        };
+
+        let terms = stemmed_query
+            .iter()
+            .map(|term| TermFeatures {
+                term: term.clone(),
+                occurences: occurences_in(&self.database, term),
+                anchor_occurences: occurences_in(&self.anchor_database, term),
+                main_occurences: occurences_in(&self.main_database, term),
+                in_title: title_words.contains(term),
+                in_description: description_words.contains(term),
+                in_url: url_words.contains(term),
+                in_headings: heading_words.contains(term),
+                in_keywords: keyword_words.contains(term),
+                in_image_alts: image_alt_words.contains(term),
+            })
+            .collect();
+
+        DocumentFeatures {
+            url: url.to_string(),
+            terms,
+            url_priority: document
+                .map_or_else(|| Self::calculate_url_priority(url), |d| d.url_priority),
+            inlinks: self.inlinks.get(url).copied().unwrap_or(0),
+            age_secs: document.map(|d| now().saturating_sub(d.crawled_at())),
+        }
+    }
+
+    //url quality signal: homepages and top-level pages should beat deep archive pages
+    fn calculate_url_priority(url: &str) -> u32 {
+        let url = match Url::parse(url) {
+            Err(_) => return 0,
+            Ok(url) => url,
+        };
+
+        let depth = url
+            .path_segments()
+            .map_or(0, |segments| segments.filter(|s| !s.is_empty()).count());
+
+        let mut priority: i64 = 10;
+        priority -= 2 * depth as i64; //deeper pages are less important
+        if depth == 0 {
+            priority += 5; //domain root
+        }
+        if url.query().is_some() {
+            priority -= 3;
+        }
+        priority -= (url.as_str().len() / 25) as i64; //long urls are usually generated ones
+
+        priority.max(0) as u32
+    }
+}
+
+//takes count off the url, dropping it once it gets to 0
+fn decrement(counts: &mut HashMap<String, u32>, url: &str, count: u32) {
+    if let Some(current) = counts.get_mut(url) {
+        *current = current.saturating_sub(count);
+        if *current == 0 {
+            counts.remove(url);
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
 }
 
 impl crate::Indexer for IndexerImplementation {
     fn insert(
         &mut self,
-        words: &[String],
         url: &str,
-        title: &Option<String>,
-        description: &Option<String>,
-        language: &Option<String>,
-        content: &str,
+        filetype: &str,
+        words: &[String],
+        main_words: &[String],
+        document: &ExtractedDocument,
+        metadata: Option<&CrawlMetadata>,
     ) -> Result<(), String> {
-        for word in words {
-            let resource_to_add = IndexedResource {
-                url: url.to_string(),
-                priority: Self::calculate_word_priority(word, content, words), //we should take into account title, description lang etc
-                word: Arc::new(word.to_string()),
-                title: title.clone(),
-                description: description.clone(),
-                language: language.clone(),
-            };
+        self.noindex.remove(url);
+        //it has content of its own now
+        self.aliases.remove(url);
+        //the words that left the page since we last indexed it shouldnt find it anymore
+        self.forget(url);
 
+        let mut occurences: HashMap<String, u32> = HashMap::new();
+        for word in words {
             let stemmed_word = self.stemmer.stem(word).to_string();
             log::debug!("Word: {}, Stemmed word: {}", word, stemmed_word);
-            match self.database.get_mut(&stemmed_word) {
-                Some(resources) => _ = resources.insert(resource_to_add),
-                None => {
-                    _ = self
-                        .database
-                        .insert(stemmed_word, HashSet::from([resource_to_add]))
+            *occurences.entry(stemmed_word).or_default() += 1;
+        }
+
+        let mut stemmed_words: HashSet<String> = occurences.keys().cloned().collect();
+        for (stemmed_word, count) in occurences {
+            self.database
+                .entry(stemmed_word)
+                .or_default()
+                .insert(url.to_string(), count);
+        }
+
+        let mut main_occurences: HashMap<String, u32> = HashMap::new();
+        for word in main_words {
+            *main_occurences
+                .entry(self.stemmer.stem(word).to_string())
+                .or_default() += 1;
+        }
+
+        stemmed_words.extend(main_occurences.keys().cloned());
+        for (stemmed_word, count) in main_occurences {
+            self.main_database
+                .entry(stemmed_word)
+                .or_default()
+                .insert(url.to_string(), count);
+        }
+
+        self.documents.insert(
+            url.to_string(),
+            IndexedDocument {
+                title: document.title.clone(),
+                description: document.description.clone(),
+                //the first language of the Content-Language header if the page doesnt say
+                language: document.language.clone().or_else(|| {
+                    metadata
+                        .and_then(|m| m.content_language.as_ref())
+                        .and_then(|l| l.split(',').next())
+                        .map(|l| l.trim().to_string())
+                        .filter(|l| !l.is_empty())
+                }),
+                filetype: filetype.to_string(),
+                url_priority: Self::calculate_url_priority(url),
+                indexed_at: now(),
+                headings: document.headings.clone(),
+                og_title: document.og_title.clone(),
+                og_description: document.og_description.clone(),
+                keywords: document.keywords.clone(),
+                image_alts: document.image_alts.clone(),
+                structured_data: document.structured_data.clone(),
+                crawl: metadata.cloned(),
+                words: Some(stemmed_words.into_iter().collect()),
+            },
+        );
+
+        Ok(())
+    }
+
+    fn remove(&mut self, url: &str) -> Result<(), String> {
+        self.forget(url);
+        self.noindex.insert(url.to_string());
+
+        Ok(())
+    }
+
+    fn add_alias(&mut self, alias: &str, url: &str) -> Result<(), String> {
+        //every recrawl sends the redirects again, we only have to move things over the first time
+        if alias == url || self.aliases.get(alias).map(String::as_str) == Some(url) {
+            return Ok(());
+        }
+        //its content is url's now, and so are its links
+        self.forget(alias);
+        self.forget_anchors(alias);
+        self.noindex.remove(alias);
+
+        //and so are the links pointing to it
+        for urls in self.anchor_database.values_mut() {
+            if let Some(count) = urls.remove(alias) {
+                *urls.entry(url.to_string()).or_default() += count;
+            }
+        }
+        if let Some(count) = self.inlinks.remove(alias) {
+            *self.inlinks.entry(url.to_string()).or_default() += count;
+        }
+        for targets in self.anchors.values_mut() {
+            if let Some(words) = targets.remove(alias) {
+                //pages linking to both only count once
+                if targets.contains_key(url) {
+                    decrement(&mut self.inlinks, url, 1);
+                }
+                let merged = targets.entry(url.to_string()).or_default();
+                for (word, count) in words {
+                    *merged.entry(word).or_default() += count;
                 }
             }
         }
 
+        for target in self.aliases.values_mut() {
+            if target == alias {
+                *target = url.to_string();
+            }
+        }
+        self.aliases.remove(url);
+        self.aliases.insert(alias.to_string(), url.to_string());
+
+        Ok(())
+    }
+
+    fn insert_anchor_texts(
+        &mut self,
+        source_url: &str,
+        anchors: &[(String, Vec<String>)],
+    ) -> Result<(), String> {
+        //take out what the previous version of the page added
+        self.forget_anchors(source_url);
+
+        let mut targets: HashMap<String, HashMap<String, u32>> = HashMap::new();
+        for (target_url, words) in anchors {
+            let occurences = targets
+                .entry(self.resolve(target_url).to_string())
+                .or_default();
+            for word in words {
+                *occurences
+                    .entry(self.stemmer.stem(word).to_string())
+                    .or_default() += 1;
+            }
+        }
+
+        for (target_url, words) in &targets {
+            for (stemmed_word, count) in words {
+                *self
+                    .anchor_database
+                    .entry(stemmed_word.clone())
+                    .or_default()
+                    .entry(target_url.clone())
+                    .or_default() += count;
+            }
+            *self.inlinks.entry(target_url.clone()).or_default() += 1;
+        }
+        if !targets.is_empty() {
+            self.anchors.insert(source_url.to_string(), targets);
+        }
+
         Ok(())
     }
 
     fn search(&self, term: &str) -> Result<HashSet<IndexedResource>, String> {
-        let query: Vec<&str> = term.split(' ').collect();
-
-        //percentage of valid words
-        let mut valid_results: Option<HashSet<IndexedResource>> = None;
-        for w in query {
-            //Normalise queries to lowercase
-            let w = w.to_ascii_lowercase();
-
-            let stemmed_word = self.stemmer.stem(&w).to_string();
-            let curr_word_results = match self.search_word_in_db(stemmed_word) {
-                None => return Ok(HashSet::new()), //I dont really like this
-                Some(curr_results) => curr_results,
+        let (query, stemmed_query) = self.normalise_query(term);
+
+        //only the documents matching every word are valid results
+        let mut valid_urls: Option<HashSet<&String>> = None;
+        for stemmed_word in &stemmed_query {
+            let curr_word_urls = self.search_word_in_db(stemmed_word);
+
+            valid_urls = match valid_urls {
+                None => Some(curr_word_urls),
+                Some(urls) => Some(urls.intersection(&curr_word_urls).copied().collect()),
             };
+        }
 
-            match valid_results {
-                //Initialise valid_results
-                None => {
-                    valid_results = Some(curr_word_results.to_owned());
-                }
-                Some(results) => {
-                    let intersection: HashSet<IndexedResource> = curr_word_results
-                        .intersection(&results)
-                        .map(|s| s.to_owned())
-                        .collect();
-                    valid_results = Some(intersection);
+        let candidates: Vec<DocumentFeatures> = valid_urls
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|url| !self.noindex.contains(*url))
+            .map(|url| self.calculate_features(url, &stemmed_query))
+            .collect();
+        let scores = self.rankers.score(&query, &candidates);
+
+        let word = Arc::new(term.to_string());
+        Ok(candidates
+            .into_iter()
+            .zip(scores)
+            .map(|(candidate, score)| {
+                let document = self.documents.get(&candidate.url);
+                IndexedResource {
+                    url: candidate.url,
+                    title: document.and_then(|d| d.display_title().cloned()),
+                    description: document.and_then(|d| d.display_description().cloned()),
+                    priority: score.max(0.0).round() as u32,
+                    score,
+                    word: word.clone(),
+                    language: document.and_then(|d| d.language.clone()),
+                    filetype: document.map_or_else(default_filetype, |d| d.filetype.clone()),
+                    author: document.and_then(|d| d.structured_data.author.clone()),
+                    date_published: document.and_then(|d| d.structured_data.date_published.clone()),
+                    crawled_at: document.map(|d| d.crawled_at()),
                 }
-            }
+            })
+            .collect())
+    }
+
+    fn explain(&self, term: &str, url: &str) -> Result<ScoreExplanation, String> {
+        let url = self.resolve(url);
+        if !self.documents.contains_key(url) && !self.inlinks.contains_key(url) {
+            return Err(format!("Unknown url: {url}"));
         }
 
-        Ok(valid_results.unwrap())
+        let (query, stemmed_query) = self.normalise_query(term);
+        let features = self.calculate_features(url, &stemmed_query);
+
+        let rankers: Vec<RankerExplanation> = self
+            .rankers
+            .score_by_ranker(&query, &features)
+            .into_iter()
+            .map(|(name, weight, score)| RankerExplanation {
+                name: name.to_string(),
+                weight,
+                score,
+            })
+            .collect();
+        let score = rankers.iter().map(|r| r.weight * r.score).sum();
+
+        //a term's contribution is how much the score grows when only that term is taken into account
+        let score_with_terms = |terms: Vec<TermFeatures>| {
+            let features = DocumentFeatures {
+                terms,
+                ..features.clone()
+            };
+            self.rankers.score(&query, &[features])[0]
+        };
+        let base_score = score_with_terms(vec![]);
+
+        let terms = features
+            .terms
+            .iter()
+            .map(|t| TermExplanation {
+                term: t.term.clone(),
+                occurences: t.occurences,
+                anchor_occurences: t.anchor_occurences,
+                main_occurences: t.main_occurences,
+                in_title: t.in_title,
+                in_description: t.in_description,
+                in_url: t.in_url,
+                in_headings: t.in_headings,
+                in_keywords: t.in_keywords,
+                in_image_alts: t.in_image_alts,
+                contribution: score_with_terms(vec![t.clone()]) - base_score,
+            })
+            .collect();
+
+        let signal = |name: &str, value: f64| SignalExplanation {
+            name: name.to_string(),
+            value,
+        };
+        let mut signals = vec![
+            signal("url_priority", features.url_priority as f64),
+            signal("inlinks", features.inlinks as f64),
+            signal("base_score", base_score),
+        ];
+        if let Some(age) = features.age_secs {
+            signals.push(signal("age_secs", age as f64));
+        }
+        //to debug bad documents
+        if let Some(crawl) = self.documents.get(url).and_then(|d| d.crawl.as_ref()) {
+            signals.push(signal("status", crawl.status as f64));
+            signals.push(signal("size_bytes", crawl.size as f64));
+        }
+
+        Ok(ScoreExplanation {
+            url: url.to_string(),
+            query: term.to_string(),
+            score,
+            matches_query: features
+                .terms
+                .iter()
+                .all(|t| t.occurences > 0 || t.anchor_occurences > 0),
+            rankers,
+            terms,
+            signals,
+        })
     }
 
     fn num_of_words(&self) -> usize {
         self.database.len()
     }
+
+    fn save(&self, path: &str) -> Result<(), String> {
+        //we write to a temporary file first so a crash doesnt leave a half written index
+        let tmp_path = format!("{path}.tmp");
+        let file =
+            File::create(&tmp_path).map_err(|e| format!("Error creating {tmp_path}: {e}"))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, self)
+            .map_err(|e| format!("Error writing index {tmp_path}: {e}"))?;
+        //only replace the old index once the new one is safely on disk
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .and_then(|file| file.sync_all())
+            .map_err(|e| format!("Error writing index {tmp_path}: {e}"))?;
+        std::fs::rename(&tmp_path, path).map_err(|e| format!("Error saving index {path}: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ranking::DEFAULT_RANKERS;
+    use crate::Indexer;
+
+    fn new_indexer() -> IndexerImplementation {
+        IndexerImplementation::new(RankerChain::from_spec(DEFAULT_RANKERS).unwrap())
+    }
+
+    //indexes a page with that text and links (target url, anchor text)
+    fn index(indexer: &mut IndexerImplementation, url: &str, text: &str, links: &[(&str, &str)]) {
+        let words: Vec<String> = text.split_whitespace().map(String::from).collect();
+        let document = ExtractedDocument::default();
+        indexer
+            .insert(url, "html", &words, &words, &document, None)
+            .unwrap();
+        let anchors: Vec<(String, Vec<String>)> = links
+            .iter()
+            .map(|(target, anchor)| {
+                let words = anchor.split_whitespace().map(String::from).collect();
+                (target.to_string(), words)
+            })
+            .collect();
+        indexer.insert_anchor_texts(url, &anchors).unwrap();
+    }
+
+    fn search(indexer: &IndexerImplementation, term: &str) -> Vec<String> {
+        let mut urls: Vec<String> = indexer
+            .search(term)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.url)
+            .collect();
+        urls.sort();
+        urls
+    }
+
+    fn anchor_counts(indexer: &IndexerImplementation, word: &str) -> HashMap<String, u32> {
+        indexer
+            .anchor_database
+            .get(word)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn reindexing_forgets_the_old_words() {
+        let mut indexer = new_indexer();
+        index(&mut indexer, "http://a/", "alpha beta", &[]);
+        index(&mut indexer, "http://a/", "beta gamma", &[]);
+
+        assert!(search(&indexer, "alpha").is_empty());
+        assert_eq!(search(&indexer, "beta"), vec!["http://a/"]);
+        assert_eq!(search(&indexer, "gamma"), vec!["http://a/"]);
+        assert!(!indexer.database.contains_key("alpha"));
+        assert!(!indexer.main_database.contains_key("alpha"));
+    }
+
+    #[test]
+    fn reindexing_replaces_the_anchor_text() {
+        let mut indexer = new_indexer();
+        index(
+            &mut indexer,
+            "http://s/",
+            "source",
+            &[("http://t/", "kiwi")],
+        );
+        index(
+            &mut indexer,
+            "http://s/",
+            "source",
+            &[("http://t/", "kiwi")],
+        );
+
+        assert_eq!(
+            anchor_counts(&indexer, "kiwi"),
+            HashMap::from([("http://t/".to_string(), 1)])
+        );
+        assert_eq!(indexer.inlinks.get("http://t/"), Some(&1));
+
+        //the link is gone from the page
+        index(&mut indexer, "http://s/", "source", &[]);
+        assert!(!indexer.anchor_database.contains_key("kiwi"));
+        assert!(!indexer.inlinks.contains_key("http://t/"));
+        assert!(search(&indexer, "kiwi").is_empty());
+    }
+
+    #[test]
+    fn links_from_several_pages_add_up() {
+        let mut indexer = new_indexer();
+        index(&mut indexer, "http://s1/", "one", &[("http://t/", "kiwi")]);
+        index(
+            &mut indexer,
+            "http://s2/",
+            "two",
+            &[("http://t/", "kiwi fruit")],
+        );
+        index(&mut indexer, "http://s1/", "one", &[("http://t/", "kiwi")]);
+
+        assert_eq!(indexer.inlinks.get("http://t/"), Some(&2));
+        assert_eq!(anchor_counts(&indexer, "kiwi")["http://t/"], 2);
+        assert_eq!(search(&indexer, "fruit"), vec!["http://t/"]);
+    }
+
+    #[test]
+    fn aliases_take_over_the_links_pointing_at_them() {
+        let mut indexer = new_indexer();
+        index(
+            &mut indexer,
+            "http://s/",
+            "source",
+            &[("http://old/", "kiwi")],
+        );
+        index(&mut indexer, "http://old/", "old content", &[]);
+
+        indexer.add_alias("http://old/", "http://new/").unwrap();
+        index(&mut indexer, "http://new/", "new content", &[]);
+
+        assert_eq!(search(&indexer, "kiwi"), vec!["http://new/"]);
+        assert_eq!(search(&indexer, "content"), vec!["http://new/"]);
+        assert!(search(&indexer, "old").is_empty());
+        assert_eq!(indexer.inlinks.get("http://new/"), Some(&1));
+        assert!(!indexer.inlinks.contains_key("http://old/"));
+
+        //links to the alias are resolved from now on
+        index(
+            &mut indexer,
+            "http://s/",
+            "source",
+            &[("http://old/", "kiwi")],
+        );
+        assert_eq!(indexer.inlinks.get("http://new/"), Some(&1));
+        assert_eq!(
+            anchor_counts(&indexer, "kiwi"),
+            HashMap::from([("http://new/".to_string(), 1)])
+        );
+        let explanation = indexer.explain("kiwi", "http://old/").unwrap();
+        assert_eq!(explanation.url, "http://new/");
+    }
+
+    #[test]
+    fn aliases_drop_the_links_they_had() {
+        //a page crawled over http that later redirects to https
+        let mut indexer = new_indexer();
+        index(&mut indexer, "http://a/", "page", &[("http://t/", "rust")]);
+        indexer.add_alias("http://a/", "https://a/").unwrap();
+        index(&mut indexer, "https://a/", "page", &[("http://t/", "rust")]);
+        //recrawls send the redirect again
+        indexer.add_alias("http://a/", "https://a/").unwrap();
+
+        assert_eq!(indexer.inlinks.get("http://t/"), Some(&1));
+        assert_eq!(
+            anchor_counts(&indexer, "rust"),
+            HashMap::from([("http://t/".to_string(), 1)])
+        );
+        assert_eq!(
+            indexer.anchors.keys().collect::<Vec<_>>(),
+            vec!["https://a/"]
+        );
+        assert_eq!(search(&indexer, "page"), vec!["https://a/"]);
+    }
+
+    #[test]
+    fn chained_aliases_resolve_to_the_last_url() {
+        let mut indexer = new_indexer();
+        indexer.add_alias("http://a/", "http://b/").unwrap();
+        indexer.add_alias("http://b/", "http://c/").unwrap();
+        index(
+            &mut indexer,
+            "http://s/",
+            "source",
+            &[("http://a/", "kiwi")],
+        );
+
+        assert_eq!(indexer.aliases["http://a/"], "http://c/");
+        assert_eq!(search(&indexer, "kiwi"), vec!["http://c/"]);
+    }
+
+    #[test]
+    fn indexed_urls_stop_being_aliases() {
+        let mut indexer = new_indexer();
+        indexer.add_alias("http://a/", "http://b/").unwrap();
+        index(&mut indexer, "http://a/", "own content", &[]);
+        index(
+            &mut indexer,
+            "http://s/",
+            "source",
+            &[("http://a/", "kiwi")],
+        );
+
+        assert!(!indexer.aliases.contains_key("http://a/"));
+        assert_eq!(search(&indexer, "kiwi"), vec!["http://a/"]);
+    }
+
+    #[test]
+    fn noindex_pages_are_removed_and_kept_out_of_the_results() {
+        let mut indexer = new_indexer();
+        index(&mut indexer, "http://p/", "secret", &[]);
+        index(
+            &mut indexer,
+            "http://s/",
+            "source",
+            &[("http://p/", "hidden")],
+        );
+        assert_eq!(search(&indexer, "secret"), vec!["http://p/"]);
+
+        indexer.remove("http://p/").unwrap();
+        assert!(search(&indexer, "secret").is_empty());
+        assert!(search(&indexer, "hidden").is_empty());
+        assert!(!indexer.database.contains_key("secret"));
+        assert!(!indexer.documents.contains_key("http://p/"));
+
+        //until it allows indexing again
+        index(&mut indexer, "http://p/", "secret", &[]);
+        assert_eq!(search(&indexer, "secret"), vec!["http://p/"]);
+        assert_eq!(search(&indexer, "hidden"), vec!["http://p/"]);
+    }
+
+    #[test]
+    fn removing_unknown_urls_keeps_them_out_of_the_results() {
+        let mut indexer = new_indexer();
+        indexer.remove("http://p/").unwrap();
+        index(
+            &mut indexer,
+            "http://s/",
+            "source",
+            &[("http://p/", "hidden")],
+        );
+        assert!(search(&indexer, "hidden").is_empty());
+    }
+
+    #[test]
+    fn indexes_saved_without_word_lists_are_still_forgotten() {
+        let mut indexer = new_indexer();
+        index(&mut indexer, "http://a/", "alpha", &[]);
+        indexer.documents.get_mut("http://a/").unwrap().words = None;
+
+        index(&mut indexer, "http://a/", "beta", &[]);
+        assert!(search(&indexer, "alpha").is_empty());
+        assert_eq!(search(&indexer, "beta"), vec!["http://a/"]);
+    }
 }
@@ -0,0 +1,31 @@
+pub mod indexer_implementation;
+pub mod ranking;
+
+use lib::lib::*;
+use std::collections::HashSet;
+
+pub trait Indexer {
+    fn insert(
+        &mut self,
+        url: &str,
+        filetype: &str,
+        words: &[String],
+        main_words: &[String], //the words of the main content (without the boilerplate)
+        document: &ExtractedDocument,
+        metadata: Option<&CrawlMetadata>, //none if it wasnt sent by a crawler
+    ) -> Result<(), String>;
+    //forgets the page and keeps it out of the results (noindex)
+    fn remove(&mut self, url: &str) -> Result<(), String>;
+    //the alias redirects to url, so whatever we know about it belongs to url
+    fn add_alias(&mut self, alias: &str, url: &str) -> Result<(), String>;
+    //the anchor text (target url, words) of the links of the source page, replacing what it said before
+    fn insert_anchor_texts(
+        &mut self,
+        source_url: &str,
+        anchors: &[(String, Vec<String>)],
+    ) -> Result<(), String>;
+    fn search(&self, term: &str) -> Result<HashSet<IndexedResource>, String>;
+    fn explain(&self, term: &str, url: &str) -> Result<ScoreExplanation, String>;
+    fn num_of_words(&self) -> usize;
+    fn save(&self, path: &str) -> Result<(), String>;
+}
@@ -1,28 +1,18 @@
-mod indexer_implementation;
-
 use actix_cors::Cors;
-use actix_web::{post, web, App, HttpRequest, HttpServer, Responder};
-use indexer_implementation::IndexerImplementation;
-use kuchiki::traits::TendrilSink;
+use actix_web::http::header;
+use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use indexer::indexer_implementation::IndexerImplementation;
+use indexer::ranking::RankerChain;
+use indexer::Indexer;
+use lib::extraction;
 use lib::lib::*;
 use serde::Deserialize;
-use std::collections::HashSet;
+use std::path::Path;
 use std::sync::Mutex;
+use url::Url;
 
-pub trait Indexer {
-    //too many args?
-    fn insert(
-        &mut self,
-        words: &[String],
-        url: &str,
-        title: &Option<String>,
-        description: &Option<String>,
-        language: &Option<String>,
-        content: &str,
-    ) -> Result<(), String>;
-    fn search(&self, term: &str) -> Result<HashSet<IndexedResource>, String>;
-    fn num_of_words(&self) -> usize;
-}
+//biggest (uncompressed) body we accept in /api/resources
+const MAX_BATCH_PAYLOAD_SIZE: usize = 256 * 1024 * 1024;
 
 struct AppState {
     indexer: Mutex<Box<dyn Indexer + Send + Sync>>,
@@ -34,18 +24,58 @@ async fn main() -> std::io::Result<()> {
 
     log::info!("Hello, world! Im the indexer!");
 
-    serve_http_endpoint("0.0.0.0", 4444).await
-}
+    //the ranking chain can be customised with OSSE_RANKERS="ranker:weight,..."
+    let rankers = match std::env::var("OSSE_RANKERS") {
+        Err(_) => RankerChain::default(),
+        Ok(spec) => RankerChain::from_spec(&spec)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+    };
+
+    log::info!("Ranking with: {}", rankers.describe());
+
+    //if OSSE_INDEX_PATH is set, the index is loaded from it at startup and saved to it on shutdown
+    let index_path = std::env::var("OSSE_INDEX_PATH").ok();
+    let indexer = match &index_path {
+        Some(path) if Path::new(path).exists() => {
+            log::info!("Loading index from {}", path);
+            IndexerImplementation::load(path, rankers)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+        }
+        _ => IndexerImplementation::new(rankers),
+    };
 
-async fn serve_http_endpoint(address: &str, port: u16) -> std::io::Result<()> {
     let shared_state = web::Data::new(AppState {
-        indexer: Mutex::new(Box::new(IndexerImplementation::new())), //maybe mutex is not the best option
+        indexer: Mutex::new(Box::new(indexer)), //maybe mutex is not the best option
     });
+
+    serve_http_endpoint("0.0.0.0", 4444, shared_state.clone()).await?;
+
+    if let Some(path) = index_path {
+        log::info!("Saving index to {}", path);
+        shared_state
+            .indexer
+            .lock()
+            .unwrap()
+            .save(&path)
+            .map_err(std::io::Error::other)?;
+    }
+
+    Ok(())
+}
+
+async fn serve_http_endpoint(
+    address: &str,
+    port: u16,
+    shared_state: web::Data<AppState>,
+) -> std::io::Result<()> {
     HttpServer::new(move || {
         App::new()
             .wrap(Cors::permissive())
             .app_data(shared_state.clone())
+            .app_data(web::PayloadConfig::new(MAX_BATCH_PAYLOAD_SIZE))
             .service(add_resource)
+            .service(add_resources)
+            .service(explain)
             .service(
                 web::resource(["/api/search", "/api/search/", "/api/search/{query}"]).to(search),
             )
@@ -63,88 +93,151 @@ async fn add_resource(
     data: web::Data<AppState>,
     resource: web::Json<CrawledResource>,
 ) -> impl Responder {
-    //parse content
-    let document = scraper::Html::parse_document(resource.content.as_str());
-    let kuchiki_parser = kuchiki::parse_html().one(resource.content.as_str());
-
-    //remove script, style and noscript tags
-    kuchiki_parser
-        .inclusive_descendants()
-        .filter(|node| {
-            node.as_element().map_or(false, |e| {
-                matches!(e.name.local.as_ref(), "script" | "style" | "noscript")
-            })
-        })
-        .collect::<Vec<_>>()
-        .iter()
-        .for_each(|node| node.detach());
+    let mut resource = resource.into_inner();
+    //parse content (unless the crawler already did)
+    let document = extract_document(&mut resource);
 
-    let text = kuchiki_parser.text_contents();
-
-    let split_words = text.split(' ');
+    let mut indexer = data.indexer.lock().unwrap();
+    insert_resource(indexer.as_mut(), &resource, &document);
 
-    //fixup words (remove words with non alphabetic chars, empty words, transform to lowercase...)
-    let fixed_words: Vec<String> = split_words
-        .map(|w| w.to_ascii_lowercase().split_whitespace().collect())
-        .filter(|w: &String| !w.is_empty())
-        .collect();
+    //TODO: ADD LANG? EN in meta tag (frontend)
+    //Now what to do, global lang?, per index lang?, website lang?
+    //TODO: max number of results in query
 
-    log::debug!("xd: {:?}", fixed_words);
+    log::debug!("Added resource: {:?}", indexer.num_of_words());
 
-    let title_selector = scraper::Selector::parse("title").unwrap();
-    let meta_selector = scraper::Selector::parse("meta").unwrap();
-    let html_selector = scraper::Selector::parse("html").unwrap();
+    format!("{resource:?}")
+}
 
-    let page_title: Option<String> = match document
-        .select(&title_selector)
-        .map(|e| e.inner_html())
-        .take(1)
-        .collect::<String>()
-    {
-        s if s.is_empty() => None,
-        string => Some(html_escape::decode_html_entities(&string).to_string()),
+//same as /api/resource but for many resources at once, sent as a json array or as ndjson (one per line).
+//the body can be gzip compressed (Content-Encoding: gzip). we parse every resource before taking the lock.
+#[post("/api/resources")]
+async fn add_resources(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Bytes,
+) -> impl Responder {
+    let mut resources = match parse_batch(&req, &body) {
+        Err(e) => return HttpResponse::BadRequest().body(e),
+        Ok(resources) => resources,
     };
 
-    let page_description: Option<String> = match document
-        .select(&meta_selector)
-        .filter(|e| e.value().attr("name") == Some("description"))
-        .filter_map(|e| e.value().attr("content"))
-        .take(1)
-        .collect::<String>()
-    {
-        s if s.is_empty() => None,
-        string => Some(html_escape::decode_html_entities(&string).to_string()),
-    };
+    let documents: Vec<ExtractedDocument> = resources.iter_mut().map(extract_document).collect();
 
-    //TODO: rewrite with if let else
-    let page_language: Option<String> = match document
-        .select(&html_selector)
-        .filter_map(|e| e.value().attr("lang"))
-        .take(1)
-        .collect::<String>()
-    {
-        s if s.is_empty() => None,
-        string => Some(string),
-    };
+    let mut indexer = data.indexer.lock().unwrap();
+    for (resource, document) in resources.iter().zip(&documents) {
+        insert_resource(indexer.as_mut(), resource, document);
+    }
+
+    log::debug!(
+        "Added {} resources: {:?}",
+        resources.len(),
+        indexer.num_of_words()
+    );
+
+    HttpResponse::Ok().json(serde_json::json!({ "indexed": resources.len() }))
+}
+
+fn parse_batch(req: &HttpRequest, body: &[u8]) -> Result<Vec<CrawledResource>, String> {
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    if content_type.starts_with("application/x-ndjson") {
+        let body = std::str::from_utf8(body).map_err(|e| format!("Invalid utf-8: {e}"))?;
+        body.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| format!("Invalid resource at line {}: {e}", i + 1))
+            })
+            .collect()
+    } else {
+        serde_json::from_slice(body).map_err(|e| format!("Invalid resources: {e}"))
+    }
+}
+
+fn extract_document(resource: &mut CrawledResource) -> ExtractedDocument {
+    match resource.document.take() {
+        Some(document) => document,
+        None if resource.filetype == FILETYPE_HTML => {
+            extraction::extract_html(&resource.content, Url::parse(&resource.url).ok().as_ref())
+        }
+        //the crawler already extracted the text of other documents
+        None => extraction::extract_text(&resource.content),
+    }
+}
+
+fn insert_resource(
+    indexer: &mut dyn Indexer,
+    resource: &CrawledResource,
+    document: &ExtractedDocument,
+) {
+    //the crawler got here through redirects, the urls it started from resolve to this one
+    let redirects = resource.metadata.iter().flat_map(|m| &m.redirects);
+    for alias in redirects {
+        let _ = indexer.add_alias(alias, &resource.url);
+    }
+
+    //the page doesnt want to be indexed (the crawler tells us, or its robots meta tag if we parsed it)
+    if resource.noindex || document.robots.noindex {
+        log::debug!("Removing {} (noindex)", resource.url);
+        let _ = indexer.remove(&resource.url);
+        return;
+    }
+
+    //the metadata isnt part of the text, but the page should be found by it too
+    let data = &document.structured_data;
+    let metadata = [
+        &document.og_title,
+        &document.og_description,
+        &data.name,
+        &data.description,
+    ]
+    .into_iter()
+    .flatten()
+    .chain(&document.keywords)
+    .chain(&document.image_alts)
+    .map(String::as_str)
+    .collect::<Vec<_>>()
+    .join(" ");
+    let words = fixup_words(&format!("{} {}", document.text, metadata));
+    log::debug!("xd: {:?}", words);
+    let main_words = fixup_words(&document.main_text);
 
     //and for each changed content word we add it to the db (word -> list.append(url))
-    let mut indexer = data.indexer.lock().unwrap();
     let _ = indexer.insert(
-        &fixed_words,
         &resource.url,
-        &page_title,
-        &page_description,
-        &page_language,
-        &resource.content,
+        &resource.filetype,
+        &words,
+        &main_words,
+        document,
+        resource.metadata.as_ref(),
     );
 
-    //TODO: ADD LANG? EN in meta tag (frontend)
-    //Now what to do, global lang?, per index lang?, website lang?
-    //TODO: max number of results in query
-
-    log::debug!("Added resource: {:?}", indexer.num_of_words());
+    //index the anchor text of the outgoing links as part of the pages they point to.
+    //crawlers that send the raw html send the links they kept separately
+    let links = match resource.links.is_empty() {
+        true => &document.links,
+        false => &resource.links,
+    };
+    let anchors: Vec<(String, Vec<String>)> = links
+        .iter()
+        .filter(|l| !l.nofollow && !l.anchor_text.is_empty())
+        .map(|l| (l.url.clone(), fixup_words(&l.anchor_text)))
+        .collect();
+    let _ = indexer.insert_anchor_texts(&resource.url, &anchors);
+}
 
-    format!("{resource:?}")
+//fixup words (remove words with non alphabetic chars, empty words, transform to lowercase...)
+fn fixup_words(text: &str) -> Vec<String> {
+    text.split(' ')
+        .map(|w| w.to_ascii_lowercase().split_whitespace().collect())
+        .filter(|w: &String| !w.is_empty())
+        .collect()
 }
 
 #[derive(Debug, Deserialize)]
@@ -169,3 +262,20 @@ async fn search(
 
     serde_json::to_string(&results.unwrap()).unwrap()
 }
+
+#[derive(Debug, Deserialize)]
+struct ExplainQuery {
+    q: String,
+    url: String,
+}
+
+//returns how the score of a document for a query was computed (for debugging relevance)
+#[get("/api/explain")]
+async fn explain(data: web::Data<AppState>, query: web::Query<ExplainQuery>) -> impl Responder {
+    log::debug!("Explain: {:?}", query);
+
+    match data.indexer.lock().unwrap().explain(&query.q, &query.url) {
+        Err(e) => HttpResponse::NotFound().body(e),
+        Ok(explanation) => HttpResponse::Ok().json(explanation),
+    }
+}
@@ -0,0 +1,312 @@
+//rankers used by default when no chain is configured
+pub const DEFAULT_RANKERS: &str =
+    "term_frequency:1,main_content:2,anchor_text:3,field_match:2,url:1,link:1,freshness:1";
+
+//features of a single query term in a candidate document
+#[derive(Debug, Clone, Default)]
+pub struct TermFeatures {
+    pub term: String,
+    pub occurences: u32,        //in the page's text
+    pub anchor_occurences: u32, //in the anchor text of links pointing to the page
+    pub main_occurences: u32,   //in the main content of the page (also counted in occurences)
+    pub in_title: bool,
+    pub in_description: bool,
+    pub in_url: bool,
+    pub in_headings: bool,
+    pub in_keywords: bool, //meta keywords and structured data keywords
+    pub in_image_alts: bool,
+}
+
+//everything a ranker can know about a candidate document for a query
+#[derive(Debug, Clone, Default)]
+pub struct DocumentFeatures {
+    pub url: String,
+    pub terms: Vec<TermFeatures>,
+    pub url_priority: u32,
+    pub inlinks: u32,
+    pub age_secs: Option<u64>, //none if the page hasnt been crawled yet (only linked to)
+}
+
+pub trait Ranker: Send + Sync {
+    fn name(&self) -> &'static str;
+    //returns one score per candidate, in the same order
+    fn rank(&self, query: &[String], candidates: &[DocumentFeatures]) -> Vec<f64>;
+}
+
+//weighted sum of the scores of a list of rankers
+pub struct RankerChain {
+    rankers: Vec<(Box<dyn Ranker>, f64)>,
+}
+
+impl RankerChain {
+    //a chain of any rankers, including ones that arent ours
+    pub fn new(rankers: Vec<(Box<dyn Ranker>, f64)>) -> Self {
+        Self { rankers }
+    }
+
+    //parses a chain like "term_frequency:1,url:0.5" (the weight defaults to 1)
+    pub fn from_spec(spec: &str) -> Result<Self, String> {
+        let mut rankers = vec![];
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, weight) = match entry.split_once(':') {
+                None => (entry, 1.0),
+                Some((name, weight)) => match weight.trim().parse::<f64>() {
+                    Err(_) => return Err(format!("Invalid weight for ranker {name}: {weight}")),
+                    Ok(weight) => (name.trim(), weight),
+                },
+            };
+            rankers.push((ranker_from_name(name)?, weight));
+        }
+
+        if rankers.is_empty() {
+            return Err("The ranker chain is empty".to_string());
+        }
+
+        Ok(Self::new(rankers))
+    }
+
+    //adds a ranker at the end of the chain, e.g. a custom one to a parsed chain
+    pub fn push(&mut self, ranker: Box<dyn Ranker>, weight: f64) {
+        self.rankers.push((ranker, weight));
+    }
+
+    pub fn score(&self, query: &[String], candidates: &[DocumentFeatures]) -> Vec<f64> {
+        let mut scores = vec![0.0; candidates.len()];
+        for (ranker, weight) in &self.rankers {
+            let ranker_scores = ranker.rank(query, candidates);
+            for (score, ranker_score) in scores.iter_mut().zip(ranker_scores) {
+                *score += weight * ranker_score;
+            }
+        }
+        scores
+    }
+
+    //per ranker (name, weight, unweighted score) of a single candidate
+    pub fn score_by_ranker(
+        &self,
+        query: &[String],
+        candidate: &DocumentFeatures,
+    ) -> Vec<(&'static str, f64, f64)> {
+        self.rankers
+            .iter()
+            .map(|(ranker, weight)| {
+                let score = ranker.rank(query, std::slice::from_ref(candidate))[0];
+                (ranker.name(), *weight, score)
+            })
+            .collect()
+    }
+
+    //human readable description of the chain, e.g. "term_frequency:1, url:0.5"
+    pub fn describe(&self) -> String {
+        self.rankers
+            .iter()
+            .map(|(ranker, weight)| format!("{}:{}", ranker.name(), weight))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+impl Default for RankerChain {
+    fn default() -> Self {
+        Self::from_spec(DEFAULT_RANKERS).unwrap()
+    }
+}
+
+fn ranker_from_name(name: &str) -> Result<Box<dyn Ranker>, String> {
+    match name {
+        "term_frequency" => Ok(Box::new(TermFrequencyRanker)),
+        "main_content" => Ok(Box::new(MainContentRanker)),
+        "anchor_text" => Ok(Box::new(AnchorTextRanker)),
+        "field_match" => Ok(Box::new(FieldMatchRanker)),
+        "url" => Ok(Box::new(UrlRanker)),
+        "link" => Ok(Box::new(LinkRanker)),
+        "freshness" => Ok(Box::new(FreshnessRanker)),
+        _ => Err(format!("Unknown ranker: {name}")),
+    }
+}
+
+//scores every candidate independently
+fn rank_each(candidates: &[DocumentFeatures], f: impl Fn(&DocumentFeatures) -> f64) -> Vec<f64> {
+    candidates.iter().map(f).collect()
+}
+
+//number of occurences of the query terms in the page
+pub struct TermFrequencyRanker;
+
+impl Ranker for TermFrequencyRanker {
+    fn name(&self) -> &'static str {
+        "term_frequency"
+    }
+
+    fn rank(&self, _query: &[String], candidates: &[DocumentFeatures]) -> Vec<f64> {
+        rank_each(candidates, |c| {
+            c.terms.iter().map(|t| t.occurences as f64).sum()
+        })
+    }
+}
+
+//number of occurences of the query terms in the main content of the page, so they count more than
+//the ones in menus, footers and the like
+pub struct MainContentRanker;
+
+impl Ranker for MainContentRanker {
+    fn name(&self) -> &'static str {
+        "main_content"
+    }
+
+    fn rank(&self, _query: &[String], candidates: &[DocumentFeatures]) -> Vec<f64> {
+        rank_each(candidates, |c| {
+            c.terms.iter().map(|t| t.main_occurences as f64).sum()
+        })
+    }
+}
+
+//number of occurences of the query terms in the anchor text of incoming links
+pub struct AnchorTextRanker;
+
+impl Ranker for AnchorTextRanker {
+    fn name(&self) -> &'static str {
+        "anchor_text"
+    }
+
+    fn rank(&self, _query: &[String], candidates: &[DocumentFeatures]) -> Vec<f64> {
+        rank_each(candidates, |c| {
+            c.terms.iter().map(|t| t.anchor_occurences as f64).sum()
+        })
+    }
+}
+
+//query terms appearing in the title, description, url, headings, keywords or image alt texts
+pub struct FieldMatchRanker;
+
+impl Ranker for FieldMatchRanker {
+    fn name(&self) -> &'static str {
+        "field_match"
+    }
+
+    fn rank(&self, _query: &[String], candidates: &[DocumentFeatures]) -> Vec<f64> {
+        rank_each(candidates, |c| {
+            c.terms
+                .iter()
+                .map(|t| {
+                    2.0 * t.in_title as u32 as f64
+                        + 1.5 * t.in_headings as u32 as f64
+                        + t.in_description as u32 as f64
+                        + t.in_url as u32 as f64
+                        + 0.5 * t.in_keywords as u32 as f64
+                        + 0.5 * t.in_image_alts as u32 as f64
+                })
+                .sum()
+        })
+    }
+}
+
+//shape of the url (depth, query, length...) computed at index time
+pub struct UrlRanker;
+
+impl Ranker for UrlRanker {
+    fn name(&self) -> &'static str {
+        "url"
+    }
+
+    fn rank(&self, _query: &[String], candidates: &[DocumentFeatures]) -> Vec<f64> {
+        rank_each(candidates, |c| c.url_priority as f64)
+    }
+}
+
+//pages with more incoming links are more important
+pub struct LinkRanker;
+
+impl Ranker for LinkRanker {
+    fn name(&self) -> &'static str {
+        "link"
+    }
+
+    fn rank(&self, _query: &[String], candidates: &[DocumentFeatures]) -> Vec<f64> {
+        rank_each(candidates, |c| (1.0 + c.inlinks as f64).ln())
+    }
+}
+
+//recently crawled pages get a small boost that decays over a few days
+pub struct FreshnessRanker;
+
+impl Ranker for FreshnessRanker {
+    fn name(&self) -> &'static str {
+        "freshness"
+    }
+
+    fn rank(&self, _query: &[String], candidates: &[DocumentFeatures]) -> Vec<f64> {
+        rank_each(candidates, |c| match c.age_secs {
+            None => 0.0,
+            Some(age) => 1.0 / (1.0 + age as f64 / (60.0 * 60.0 * 24.0)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //prefers the shortest urls
+    struct ShortUrlRanker;
+
+    impl Ranker for ShortUrlRanker {
+        fn name(&self) -> &'static str {
+            "short_url"
+        }
+
+        fn rank(&self, _query: &[String], candidates: &[DocumentFeatures]) -> Vec<f64> {
+            rank_each(candidates, |c| 1.0 / c.url.len() as f64)
+        }
+    }
+
+    fn candidate(url: &str, occurences: u32) -> DocumentFeatures {
+        DocumentFeatures {
+            url: url.to_string(),
+            terms: vec![TermFeatures {
+                term: "rust".to_string(),
+                occurences,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_chains() {
+        let chain = RankerChain::from_spec(" term_frequency:2, url ").unwrap();
+        assert_eq!(chain.describe(), "term_frequency:2, url:1");
+
+        assert!(RankerChain::from_spec("").is_err());
+        assert!(RankerChain::from_spec("unknown:1").is_err());
+        assert!(RankerChain::from_spec("url:heavy").is_err());
+    }
+
+    #[test]
+    fn scores_are_weighted_sums() {
+        let query = vec!["rust".to_string()];
+        let candidates = vec![candidate("http://a/", 1), candidate("http://a/b", 3)];
+
+        let chain = RankerChain::from_spec("term_frequency:2").unwrap();
+        assert_eq!(chain.score(&query, &candidates), vec![2.0, 6.0]);
+    }
+
+    #[test]
+    fn custom_rankers_can_be_added() {
+        let query = vec!["rust".to_string()];
+        let candidates = vec![candidate("http://a/", 1), candidate("http://a/b", 1)];
+
+        let chain = RankerChain::new(vec![(Box::new(ShortUrlRanker), 10.0)]);
+        let scores = chain.score(&query, &candidates);
+        assert!(scores[0] > scores[1]);
+
+        let mut chain = RankerChain::from_spec("term_frequency:1").unwrap();
+        chain.push(Box::new(ShortUrlRanker), 9.0);
+        assert_eq!(chain.describe(), "term_frequency:1, short_url:9");
+        let scores = chain.score(&query, &candidates);
+        assert_eq!(scores[0], 2.0);
+        assert!(scores[0] > scores[1]);
+        let explanation = chain.score_by_ranker(&query, &candidates[0]);
+        assert_eq!(explanation[1], ("short_url", 9.0, 1.0 / 9.0));
+    }
+}
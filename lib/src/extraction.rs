@@ -0,0 +1,689 @@
+use crate::lib::{CrawledLink, ExtractedDocument, RobotsDirectives, StructuredData};
+use scraper::node::Element;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+//elements whose text isnt part of the page's content
+const IGNORED_ELEMENTS: [&str; 4] = ["script", "style", "noscript", "template"];
+
+//elements that separate words, so "<p>foo</p><p>bar</p>" isnt read as "foobar"
+const BLOCK_ELEMENTS: [&str; 36] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "title",
+    "ul",
+];
+
+//elements and aria roles that are never part of the main content (menus, banners, footers...)
+const BOILERPLATE_ELEMENTS: [&str; 5] = ["nav", "footer", "aside", "form", "dialog"];
+const BOILERPLATE_ROLES: [&str; 9] = [
+    "navigation",
+    "banner",
+    "contentinfo",
+    "complementary",
+    "search",
+    "dialog",
+    "alertdialog",
+    "menu",
+    "menubar",
+];
+//class and id hints of boilerplate, these win over content hints (a "cookie-banner-content" is
+//still a cookie banner)
+const BOILERPLATE_HINTS: [&str; 12] = [
+    "cookie",
+    "consent",
+    "banner",
+    "navbar",
+    "menu",
+    "breadcrumb",
+    "sidebar",
+    "footer",
+    "advert",
+    "popup",
+    "modal",
+    "newsletter",
+];
+//vaguer hints of boilerplate, unless they also hint at content (like readability does)
+const WEAK_BOILERPLATE_HINTS: [&str; 4] = ["social", "share", "subscribe", "comment"];
+const CONTENT_HINTS: [&str; 6] = ["article", "content", "main", "post", "entry", "text"];
+
+//elements whose text is scored to find the main content when the page doesnt mark it
+const PARAGRAPH_ELEMENTS: [&str; 4] = ["p", "pre", "td", "blockquote"];
+//shorter paragraphs are usually captions, buttons and the like
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+//so a page cant make us store an unbounded amount of metadata
+const MAX_KEYWORDS: usize = 50;
+const MAX_IMAGE_ALTS: usize = 100;
+
+const MAX_TEXT_TITLE_CHARS: usize = 100;
+const MAX_TEXT_DESCRIPTION_CHARS: usize = 300;
+
+//parses an html page, links are resolved against the page's url (relative ones are dropped without it)
+pub fn extract_html(html: &str, url: Option<&Url>) -> ExtractedDocument {
+    let document = Html::parse_document(html);
+    let selector = |selector: &str| Selector::parse(selector).unwrap();
+
+    let mut text = String::new();
+    collect_text(document.root_element(), &mut text, &|_, _| false);
+
+    let title = document
+        .select(&selector("title"))
+        .next()
+        .map(|e| collapse_whitespace(&e.text().collect::<String>()))
+        .filter(|t| !t.is_empty());
+
+    //<meta name="..." content="..."> (open graph uses property instead of name)
+    let meta = |name: &str| {
+        document
+            .select(&selector("meta"))
+            .filter(|e| {
+                let key = e
+                    .value()
+                    .attr("name")
+                    .or_else(|| e.value().attr("property"));
+                key.is_some_and(|k| k.trim().eq_ignore_ascii_case(name))
+            })
+            .filter_map(|e| e.value().attr("content"))
+            .map(collapse_whitespace)
+            .find(|c| !c.is_empty())
+    };
+
+    let description = meta("description");
+
+    let language = document
+        .select(&selector("html"))
+        .filter_map(|e| e.value().attr("lang"))
+        .map(|l| l.trim().to_string())
+        .find(|l| !l.is_empty());
+
+    let headings = document
+        .select(&selector("h1, h2, h3, h4, h5, h6"))
+        .map(|e| collapse_whitespace(&e.text().collect::<String>()))
+        .filter(|h| !h.is_empty())
+        .collect();
+
+    let (structured_data, structured_keywords) = structured_data(&document);
+
+    let mut keywords: Vec<String> = meta("keywords")
+        .iter()
+        .flat_map(|k| k.split(','))
+        .map(str::to_string)
+        .chain(structured_keywords)
+        .collect();
+    dedup_non_empty(&mut keywords, MAX_KEYWORDS);
+
+    let mut image_alts: Vec<String> = document
+        .select(&selector("img[alt]"))
+        .filter_map(|e| e.value().attr("alt"))
+        .map(str::to_string)
+        .collect();
+    dedup_non_empty(&mut image_alts, MAX_IMAGE_ALTS);
+
+    //every link of the page (in order), its anchor text is empty for image links and the like
+    let mut seen = HashSet::new();
+    let links = document
+        .select(&selector("a[href]"))
+        .filter_map(|e| {
+            let href = e.value().attr("href")?.trim();
+            let link_url = match url {
+                Some(url) => url.join(href).ok()?,
+                None => Url::parse(href).ok()?,
+            };
+            let rel = e.value().attr("rel").unwrap_or_default().to_lowercase();
+            Some(CrawledLink {
+                url: link_url.to_string(),
+                anchor_text: collapse_whitespace(&e.text().collect::<String>()),
+                nofollow: rel
+                    .split_whitespace()
+                    .any(|r| matches!(r, "nofollow" | "ugc" | "sponsored")),
+            })
+        })
+        .filter(|link| seen.insert(link.clone()))
+        .collect();
+
+    ExtractedDocument {
+        title,
+        description,
+        language,
+        text: collapse_whitespace(&text),
+        main_text: main_text(&document),
+        links,
+        headings,
+        og_title: meta("og:title"),
+        og_description: meta("og:description"),
+        keywords,
+        image_alts,
+        structured_data,
+        robots: meta("robots").map_or_else(RobotsDirectives::default, |r| robots_directives(&r)),
+    }
+}
+
+//parses directives like "noindex, nofollow" (of a robots meta tag or an X-Robots-Tag header)
+pub fn robots_directives(value: &str) -> RobotsDirectives {
+    let mut directives = RobotsDirectives::default();
+    for directive in value.split(',').map(|d| d.trim().to_lowercase()) {
+        match directive.as_str() {
+            "noindex" => directives.noindex = true,
+            "nofollow" => directives.nofollow = true,
+            "none" => {
+                directives.noindex = true;
+                directives.nofollow = true;
+            }
+            _ => {}
+        }
+    }
+    directives
+}
+
+//reads the json-ld scripts of the page, returns the metadata along with its keywords
+fn structured_data(document: &Html) -> (StructuredData, Vec<String>) {
+    let scripts = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+
+    let mut items = vec![];
+    for script in document.select(&scripts) {
+        //lots of pages have broken json-ld, nothing we can do about it
+        if let Ok(value) = serde_json::from_str(&script.text().collect::<String>()) {
+            flatten_json_ld(value, &mut items);
+        }
+    }
+
+    //the first item with each field wins, the page's main entity usually comes first
+    let string = |value: &Value| match value {
+        Value::String(s) => Some(collapse_whitespace(s)).filter(|s| !s.is_empty()),
+        _ => None,
+    };
+    let mut data = StructuredData::default();
+    let mut keywords = vec![];
+    for item in &items {
+        match item.get("@type") {
+            Some(Value::Array(types)) => data.types.extend(types.iter().filter_map(string)),
+            Some(t) => data.types.extend(string(t)),
+            None => {}
+        }
+        data.name = data.name.take().or_else(|| {
+            item.get("headline")
+                .and_then(string)
+                .or_else(|| item.get("name").and_then(string))
+        });
+        data.description = data
+            .description
+            .take()
+            .or_else(|| item.get("description").and_then(string));
+        data.date_published = data
+            .date_published
+            .take()
+            .or_else(|| item.get("datePublished").and_then(string));
+        data.author = data.author.take().or_else(|| {
+            //the author can be a name, a person or a list of them
+            let author = match item.get("author")? {
+                Value::Array(authors) => authors.first()?,
+                author => author,
+            };
+            string(author).or_else(|| author.get("name").and_then(string))
+        });
+        match item.get("keywords") {
+            Some(Value::Array(k)) => keywords.extend(k.iter().filter_map(string)),
+            Some(Value::String(k)) => keywords.extend(k.split(',').map(str::to_string)),
+            _ => {}
+        }
+    }
+    dedup_non_empty(&mut data.types, MAX_KEYWORDS);
+
+    (data, keywords)
+}
+
+//json-ld scripts can have a single item, a list of them or a @graph of them
+fn flatten_json_ld(value: Value, items: &mut Vec<Value>) {
+    match value {
+        Value::Array(values) => values.into_iter().for_each(|v| flatten_json_ld(v, items)),
+        Value::Object(mut object) => {
+            if let Some(graph) = object.remove("@graph") {
+                flatten_json_ld(graph, items);
+            }
+            items.push(Value::Object(object));
+        }
+        _ => {}
+    }
+}
+
+//trims and removes the empty and repeated (ignoring case) values, keeping at most max of them
+fn dedup_non_empty(values: &mut Vec<String>, max: usize) {
+    let mut seen = HashSet::new();
+    *values = values
+        .iter()
+        .map(|v| collapse_whitespace(v))
+        .filter(|v| !v.is_empty() && seen.insert(v.to_lowercase()))
+        .take(max)
+        .collect();
+}
+
+//plain text has no metadata, so we use its first line as the title and its beginning as the description
+pub fn extract_text(content: &str) -> ExtractedDocument {
+    let title = content
+        .lines()
+        .map(collapse_whitespace)
+        .find(|l| !l.is_empty())
+        .map(|l| first_chars(&l, MAX_TEXT_TITLE_CHARS));
+
+    let text = collapse_whitespace(content);
+    let description =
+        Some(first_chars(&text, MAX_TEXT_DESCRIPTION_CHARS)).filter(|d| !d.is_empty());
+
+    //there is no boilerplate in plain text
+    ExtractedDocument {
+        title,
+        description,
+        main_text: text.clone(),
+        text,
+        ..Default::default()
+    }
+}
+
+//the text of the main content of the page, without its menus, banners, footers and so on.
+//we trust <main> and <article> (or role="main") if the page has them, otherwise we look for the
+//element with the most text in paragraphs and the fewest links, like readability does
+fn main_text(document: &Html) -> String {
+    let select = |selector: &str| {
+        let selector = Selector::parse(selector).unwrap();
+        document
+            .select(&selector)
+            .filter(|e| !within_boilerplate(e))
+            .collect::<Vec<_>>()
+    };
+
+    let mut roots = select("main, [role=main]");
+    if roots.is_empty() {
+        roots = select("article");
+    }
+    if roots.is_empty() {
+        roots.extend(densest_element(document));
+    }
+    //we dont want the text of nested roots twice
+    let roots: Vec<ElementRef> = roots
+        .iter()
+        .filter(|root| {
+            !roots
+                .iter()
+                .any(|other| root.ancestors().any(|a| a.id() == other.id()))
+        })
+        .copied()
+        .collect();
+    let mut text = String::new();
+    match roots.is_empty() {
+        //pages without any paragraph, everything but the boilerplate
+        true => collect_text(document.root_element(), &mut text, &is_boilerplate),
+        false => {
+            for root in roots {
+                collect_text(root, &mut text, &is_boilerplate);
+                text.push(' ');
+            }
+        }
+    }
+    collapse_whitespace(&text)
+}
+
+//scores the parents of the paragraphs by how much text they have, the best one (penalised by
+//how much of its text are links) is the main content
+fn densest_element(document: &Html) -> Option<ElementRef<'_>> {
+    let paragraphs = Selector::parse(&PARAGRAPH_ELEMENTS.join(", ")).unwrap();
+
+    let mut scores: HashMap<_, f64> = HashMap::new();
+    for paragraph in document.select(&paragraphs) {
+        if within_boilerplate(&paragraph) {
+            continue;
+        }
+        let mut text = String::new();
+        collect_text(paragraph, &mut text, &is_boilerplate);
+        let text = collapse_whitespace(&text);
+        if text.chars().count() < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+
+        //longer paragraphs with more commas are more likely to be prose
+        let score = 1.0 + text.matches(',').count() as f64 + (text.len() as f64 / 100.0).min(3.0);
+        let mut ancestors = paragraph.ancestors().filter_map(ElementRef::wrap);
+        if let Some(parent) = ancestors.next() {
+            *scores.entry(parent.id()).or_default() += score;
+        }
+        if let Some(grandparent) = ancestors.next() {
+            *scores.entry(grandparent.id()).or_default() += score / 2.0;
+        }
+    }
+
+    scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let element = ElementRef::wrap(document.tree.get(id)?)?;
+            Some((element, score * (1.0 - link_density(element))))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(element, _)| element)
+}
+
+//how much of the text of the element is inside links
+fn link_density(element: ElementRef) -> f64 {
+    let text_length = |element: ElementRef| {
+        let mut text = String::new();
+        collect_text(element, &mut text, &is_boilerplate);
+        collapse_whitespace(&text).len()
+    };
+
+    let total = text_length(element);
+    if total == 0 {
+        return 1.0;
+    }
+    let links = Selector::parse("a").unwrap();
+    let in_links: usize = element.select(&links).map(text_length).sum();
+    (in_links as f64 / total as f64).min(1.0)
+}
+
+fn within_boilerplate(element: &ElementRef) -> bool {
+    let in_article = element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .any(|a| matches!(a.value().name(), "article" | "main"));
+    element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .any(|a| is_boilerplate(a.value(), in_article))
+}
+
+//whether the element is (most likely) boilerplate, headers are only boilerplate outside of articles
+fn is_boilerplate(element: &Element, in_article: bool) -> bool {
+    let name = element.name();
+    if matches!(name, "html" | "body" | "main" | "article") {
+        return false;
+    }
+    if BOILERPLATE_ELEMENTS.contains(&name) || (name == "header" && !in_article) {
+        return true;
+    }
+    if element.attr("aria-hidden") == Some("true") || element.attr("hidden").is_some() {
+        return true;
+    }
+    if let Some(role) = element.attr("role") {
+        if BOILERPLATE_ROLES.contains(&role.trim().to_lowercase().as_str()) {
+            return true;
+        }
+    }
+
+    let hints = format!(
+        "{} {}",
+        element.attr("class").unwrap_or_default(),
+        element.attr("id").unwrap_or_default()
+    )
+    .to_lowercase();
+    BOILERPLATE_HINTS.iter().any(|h| hints.contains(h))
+        || (WEAK_BOILERPLATE_HINTS.iter().any(|h| hints.contains(h))
+            && !CONTENT_HINTS.iter().any(|h| hints.contains(h)))
+}
+
+//appends the text of the element, skipping the children for which skip(element, in_article) is true
+fn collect_text(element: ElementRef, text: &mut String, skip: &dyn Fn(&Element, bool) -> bool) {
+    let in_article = matches!(element.value().name(), "article" | "main")
+        || element
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .any(|a| matches!(a.value().name(), "article" | "main"));
+    collect_text_in(element, text, skip, in_article);
+}
+
+fn collect_text_in(
+    element: ElementRef,
+    text: &mut String,
+    skip: &dyn Fn(&Element, bool) -> bool,
+    in_article: bool,
+) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(t) => text.push_str(t),
+            Node::Element(e) if IGNORED_ELEMENTS.contains(&e.name()) || skip(e, in_article) => {}
+            Node::Element(e) => {
+                let is_block = BLOCK_ELEMENTS.contains(&e.name());
+                if is_block {
+                    text.push(' ');
+                }
+                if let Some(child) = ElementRef::wrap(child) {
+                    let in_article = in_article || matches!(e.name(), "article" | "main");
+                    collect_text_in(child, text, skip, in_article);
+                }
+                if is_block {
+                    text.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn first_chars(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        None => text.to_string(),
+        Some((idx, _)) => text[..idx].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extract(html: &str) -> ExtractedDocument {
+        let url = Url::parse("https://example.com/blog/post").unwrap();
+        extract_html(html, Some(&url))
+    }
+
+    #[test]
+    fn main_text_skips_the_boilerplate() {
+        let document = extract(
+            r#"<html><body>
+                <nav><a href="/">Home</a></nav>
+                <div class="cookie-banner-content">We use cookies</div>
+                <main>
+                    <h1>Title</h1>
+                    <div class="share-content">Share this</div>
+                    <div class="main-menu">Menu</div>
+                    <p>The actual content.</p>
+                </main>
+                <footer>Copyright</footer>
+            </body></html>"#,
+        );
+        assert_eq!(document.main_text, "Title Share this The actual content.");
+        assert!(document.text.contains("We use cookies"));
+        assert!(document.text.contains("Copyright"));
+    }
+
+    #[test]
+    fn boilerplate_hints_win_over_content_hints() {
+        let document = extract(
+            r#"<html><body>
+                <div id="consent-text">Accept all</div>
+                <div class="sidebar-content">Related posts</div>
+                <div class="comment">Nice post</div>
+                <div class="entry-content">
+                    <p>A long paragraph, with enough commas, to be the main content of the page.</p>
+                </div>
+            </body></html>"#,
+        );
+        assert_eq!(
+            document.main_text,
+            "A long paragraph, with enough commas, to be the main content of the page."
+        );
+    }
+
+    #[test]
+    fn main_text_uses_articles_or_the_densest_element() {
+        let document = extract(
+            r#"<html><body>
+                <header>Site name</header>
+                <article><header>Article title</header><p>First</p></article>
+                <article><p>Second</p></article>
+            </body></html>"#,
+        );
+        assert_eq!(document.main_text, "Article title First Second");
+
+        let document = extract(
+            r#"<html><body>
+                <div><a href="/a">A link that is long enough to count as a paragraph</a></div>
+                <div id="story">
+                    <p>The first paragraph of the story, which goes on, and on, and on.</p>
+                    <p>The second paragraph of the story, which also goes on for a while.</p>
+                </div>
+            </body></html>"#,
+        );
+        assert!(document.main_text.starts_with("The first paragraph"));
+        assert!(!document.main_text.contains("A link"));
+    }
+
+    #[test]
+    fn reads_the_metadata() {
+        let document = extract(
+            r#"<html lang="en"><head>
+                <title> The   title </title>
+                <meta name="Description" content="The description">
+                <meta property="og:title" content="Open graph title">
+                <meta name="keywords" content="rust, search, Rust">
+            </head><body>
+                <h1>Heading</h1><h2></h2>
+                <img src="a.png" alt="A picture">
+            </body></html>"#,
+        );
+        assert_eq!(document.title.as_deref(), Some("The title"));
+        assert_eq!(document.description.as_deref(), Some("The description"));
+        assert_eq!(document.language.as_deref(), Some("en"));
+        assert_eq!(document.og_title.as_deref(), Some("Open graph title"));
+        assert_eq!(document.og_description, None);
+        assert_eq!(document.keywords, vec!["rust", "search"]);
+        assert_eq!(document.headings, vec!["Heading"]);
+        assert_eq!(document.image_alts, vec!["A picture"]);
+    }
+
+    #[test]
+    fn reads_json_ld() {
+        let document = extract(
+            r#"<html><head>
+                <script type="application/ld+json">not json</script>
+                <script type="application/ld+json">{
+                    "@context": "https://schema.org",
+                    "@graph": [
+                        {
+                            "@type": ["Article", "NewsArticle"],
+                            "headline": "The headline",
+                            "author": [{"@type": "Person", "name": "Jane"}],
+                            "datePublished": "2022-01-01",
+                            "keywords": ["rust", "json"]
+                        },
+                        {"@type": "WebSite", "name": "Site", "description": "The site"}
+                    ]
+                }</script>
+                <meta name="keywords" content="search">
+            </head><body></body></html>"#,
+        );
+        let data = document.structured_data;
+        assert_eq!(data.types, vec!["Article", "NewsArticle", "WebSite"]);
+        assert_eq!(data.name.as_deref(), Some("The headline"));
+        assert_eq!(data.description.as_deref(), Some("The site"));
+        assert_eq!(data.author.as_deref(), Some("Jane"));
+        assert_eq!(data.date_published.as_deref(), Some("2022-01-01"));
+        assert_eq!(document.keywords, vec!["search", "rust", "json"]);
+    }
+
+    #[test]
+    fn reads_the_robots_meta_tag() {
+        let document =
+            extract(r#"<html><head><meta name="robots" content="NoIndex"></head></html>"#);
+        assert_eq!(
+            document.robots,
+            RobotsDirectives {
+                noindex: true,
+                nofollow: false
+            }
+        );
+
+        let document = extract("<html><head></head></html>");
+        assert_eq!(document.robots, RobotsDirectives::default());
+
+        let directives = robots_directives("none");
+        assert!(directives.noindex && directives.nofollow);
+        let directives = robots_directives("index, nofollow");
+        assert!(!directives.noindex && directives.nofollow);
+    }
+
+    #[test]
+    fn resolves_the_links() {
+        let document = extract(
+            r#"<html><body>
+                <a href="other">Other <b>page</b></a>
+                <a href="other">Other <b>page</b></a>
+                <a href="https://example.org/" rel="external nofollow">Sponsor</a>
+                <a href="/image"><img src="a.png"></a>
+                <a href="http://[invalid">Broken</a>
+            </body></html>"#,
+        );
+        let links: Vec<_> = document
+            .links
+            .iter()
+            .map(|l| (l.url.as_str(), l.anchor_text.as_str(), l.nofollow))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                ("https://example.com/blog/other", "Other page", false),
+                ("https://example.org/", "Sponsor", true),
+                ("https://example.com/image", "", false),
+            ]
+        );
+
+        //relative links cant be resolved without the page's url
+        let document = extract_html(r#"<a href="other">Other</a>"#, None);
+        assert!(document.links.is_empty());
+    }
+
+    #[test]
+    fn plain_text_uses_its_first_line_as_the_title() {
+        let document = extract_text("\n  First line \nsecond   line\n");
+        assert_eq!(document.title.as_deref(), Some("First line"));
+        assert_eq!(
+            document.description.as_deref(),
+            Some("First line second line")
+        );
+        assert_eq!(document.text, document.main_text);
+    }
+}
@@ -1,3 +1,6 @@
+#[cfg(feature = "extraction")]
+pub mod extraction;
+
 pub mod lib {
 
     use serde::{Deserialize, Serialize};
@@ -5,10 +8,135 @@ pub mod lib {
     use std::hash::{Hash, Hasher};
     use std::sync::Arc;
 
+    //the kinds of documents we can index
+    pub const FILETYPE_HTML: &str = "html";
+    pub const FILETYPE_TEXT: &str = "text";
+    pub const FILETYPE_PDF: &str = "pdf";
+
+    pub fn default_filetype() -> String {
+        FILETYPE_HTML.to_string()
+    }
+
+    //"YYYY-MM-DD" of a unix time (Howard Hinnant's civil_from_days)
+    pub fn format_date(unix_time: u64) -> String {
+        let days = (unix_time / (60 * 60 * 24)) as i64 + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+        format!("{year:04}-{month:02}-{day:02}")
+    }
+
     #[derive(Serialize, Deserialize, Debug)]
     pub struct CrawledResource {
         pub url: String,
+        //raw html for html pages, the extracted text for everything else. empty if document is set
         pub content: String,
+        #[serde(default = "default_filetype")]
+        pub filetype: String,
+        //outgoing links of the page along with their anchor text
+        #[serde(default)]
+        pub links: Vec<CrawledLink>,
+        //the page already extracted by the crawler, so the indexer doesnt have to parse it
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub document: Option<ExtractedDocument>,
+        //the page doesnt want to be indexed (robots noindex), the indexer removes it if it had it
+        #[serde(default)]
+        pub noindex: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub metadata: Option<CrawlMetadata>,
+    }
+
+    //how and when the crawler fetched a resource
+    #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+    pub struct CrawlMetadata {
+        pub fetched_at: u64, //unix time
+        pub status: u16,
+        pub final_url: String, //after following the redirects
+        //the urls we were redirected from, in order (the first one is the one we requested)
+        #[serde(default)]
+        pub redirects: Vec<String>,
+        pub content_type: Option<String>,
+        pub last_modified: Option<String>,
+        pub content_language: Option<String>,
+        pub size: usize, //of the response body, in bytes
+    }
+
+    //robots meta tag and X-Robots-Tag directives we honour
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct RobotsDirectives {
+        pub noindex: bool,
+        pub nofollow: bool,
+    }
+
+    impl RobotsDirectives {
+        //the most restrictive combination of both
+        pub fn or(self, other: Self) -> Self {
+            Self {
+                noindex: self.noindex || other.noindex,
+                nofollow: self.nofollow || other.nofollow,
+            }
+        }
+    }
+
+    //what we get out of a page (see the extraction module)
+    #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+    pub struct ExtractedDocument {
+        pub title: Option<String>,
+        pub description: Option<String>,
+        pub language: Option<String>,
+        //the visible text of the page, with its whitespace collapsed
+        pub text: String,
+        //the text of the main content only (without menus, footers, banners...)
+        #[serde(default)]
+        pub main_text: String,
+        #[serde(default)]
+        pub links: Vec<CrawledLink>,
+        #[serde(default)]
+        pub headings: Vec<String>,
+        //open graph metadata, for when the title and description are missing or poor
+        pub og_title: Option<String>,
+        pub og_description: Option<String>,
+        //meta keywords and the ones of the structured data
+        #[serde(default)]
+        pub keywords: Vec<String>,
+        #[serde(default)]
+        pub image_alts: Vec<String>,
+        #[serde(default)]
+        pub structured_data: StructuredData,
+        //from <meta name="robots">
+        #[serde(default)]
+        pub robots: RobotsDirectives,
+    }
+
+    //the schema.org metadata of a page (from its json-ld scripts) we care about
+    #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+    pub struct StructuredData {
+        #[serde(default)]
+        pub types: Vec<String>, //e.g. "Article", "Product"...
+        pub name: Option<String>, //name or headline
+        pub description: Option<String>,
+        pub author: Option<String>,
+        pub date_published: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct CrawledLink {
+        pub url: String,
+        pub anchor_text: String,
+        //rel="nofollow" (or "ugc", "sponsored"), we dont crawl them nor count them
+        #[serde(default)]
+        pub nofollow: bool,
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,9 +145,64 @@ pub mod lib {
         pub title: Option<String>,
         pub description: Option<String>,
         pub priority: u32,
+        //the ranking score priority is rounded from, results are ordered by it
+        #[serde(default)]
+        pub score: f64,
         pub word: Arc<String>,
         pub language: Option<String>,
-        //maybe in the future we need filetypes?
+        #[serde(default = "default_filetype")]
+        pub filetype: String,
+        #[serde(default)]
+        pub author: Option<String>,
+        #[serde(default)]
+        pub date_published: Option<String>,
+        #[serde(default)]
+        pub crawled_at: Option<u64>, //unix time
+    }
+
+    //Breakdown of how the score of a document for a query was computed
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub struct ScoreExplanation {
+        pub url: String,
+        pub query: String,
+        pub score: f64,
+        pub matches_query: bool, //false if some query term isnt found in the document
+        pub rankers: Vec<RankerExplanation>,
+        pub terms: Vec<TermExplanation>,
+        pub signals: Vec<SignalExplanation>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub struct RankerExplanation {
+        pub name: String,
+        pub weight: f64,
+        pub score: f64, //before applying the weight
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub struct TermExplanation {
+        pub term: String,
+        pub occurences: u32,
+        pub anchor_occurences: u32,
+        #[serde(default)]
+        pub main_occurences: u32,
+        pub in_title: bool,
+        pub in_description: bool,
+        pub in_url: bool,
+        #[serde(default)]
+        pub in_headings: bool,
+        #[serde(default)]
+        pub in_keywords: bool,
+        #[serde(default)]
+        pub in_image_alts: bool,
+        pub contribution: f64,
+    }
+
+    //document level signals (not tied to any query term)
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub struct SignalExplanation {
+        pub name: String,
+        pub value: f64,
     }
 
     //We implement PartialEq, Eq and Hash to only care about the url field.
@@ -36,10 +219,14 @@ pub mod lib {
         }
     }
 
-    //Reverse ordering as priority: 1 is less than priority: 2
+    //Reverse ordering as the best results come first: score 1 is less than score 2
+    //(priority is only compared for results from indexers that dont send the score)
     impl Ord for IndexedResource {
         fn cmp(&self, other: &Self) -> Ordering {
-            self.priority.cmp(&other.priority).reverse()
+            self.score
+                .total_cmp(&other.score)
+                .then(self.priority.cmp(&other.priority))
+                .reverse()
         }
     }
 